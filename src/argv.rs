@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+/// How the user's arguments are passed to the program of an exec route.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ArgsMode {
+    /// Each whitespace-delimited word is an argument.
+    #[default]
+    Split,
+    /// The arguments are passed as one argument, unless there are none.
+    Single,
+    /// The arguments are split into words like a shell would, so that quoted
+    /// phrases are a single argument.
+    ShellWords,
+    /// The arguments are written to the program's stdin instead.
+    Stdin,
+    /// The arguments aren't passed, as the route's command already places them
+    /// where they're needed.
+    Omit,
+}
+
+impl ArgsMode {
+    /// Returns the program arguments for the user's arguments.
+    pub fn args(self, args: &str) -> Vec<String> {
+        match self {
+            Self::Split => args.split_ascii_whitespace().map(String::from).collect(),
+            Self::Single if args.is_empty() => Vec::new(),
+            Self::Single => vec![args.to_owned()],
+            Self::ShellWords => shell_words(args),
+            Self::Stdin | Self::Omit => Vec::new(),
+        }
+    }
+}
+
+/// Splits the input into words like a POSIX shell, without any expansion.
+/// Words are delimited by unquoted whitespace, single quotes preserve
+/// everything up to the next single quote, and backslashes escape the next
+/// character outside of single quotes. As the input is typed by a user rather
+/// than a script, unterminated quotes end with the input instead of failing.
+fn shell_words(input: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    // None between words, so that quoted empty strings are still words
+    let mut word: Option<String> = None;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_ascii_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => word.push('\\'),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                word.push(chars.next().unwrap_or('\\'));
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    words
+}
+
+#[cfg(test)]
+mod args {
+    use super::ArgsMode;
+
+    #[test]
+    fn split() {
+        assert_eq!(ArgsMode::Split.args("a  b c"), ["a", "b", "c"]);
+        assert!(ArgsMode::Split.args("").is_empty());
+    }
+
+    #[test]
+    fn single() {
+        assert_eq!(ArgsMode::Single.args("a  b"), ["a  b"]);
+        assert!(ArgsMode::Single.args("").is_empty());
+    }
+
+    #[test]
+    fn shell_words() {
+        assert_eq!(
+            ArgsMode::ShellWords.args(r#"a "b c" 'd "e"' f\ g"#),
+            ["a", "b c", r#"d "e""#, "f g"]
+        );
+        assert_eq!(ArgsMode::ShellWords.args(r#""a"'b'c"#), ["abc"]);
+        assert_eq!(ArgsMode::ShellWords.args(r#""a\"b\n""#), [r#"a"b\n"#]);
+        assert_eq!(ArgsMode::ShellWords.args("'' a"), ["", "a"]);
+        assert!(ArgsMode::ShellWords.args("  ").is_empty());
+    }
+
+    #[test]
+    fn shell_words_unterminated() {
+        assert_eq!(ArgsMode::ShellWords.args("a 'b c"), ["a", "b c"]);
+        assert_eq!(ArgsMode::ShellWords.args(r#"a "b c"#), ["a", "b c"]);
+        assert_eq!(ArgsMode::ShellWords.args(r"a\"), ["a\\"]);
+    }
+
+    #[test]
+    fn stdin_and_omit_pass_nothing() {
+        assert!(ArgsMode::Stdin.args("a b").is_empty());
+        assert!(ArgsMode::Omit.args("a b").is_empty());
+    }
+}
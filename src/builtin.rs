@@ -0,0 +1,361 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::iter::Peekable;
+use std::str::Chars;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime};
+
+/// The longest expression `calc` accepts, in bytes.
+const MAX_EXPRESSION_LEN: usize = 1024;
+/// How deeply `calc` expressions may nest parentheses and negations, so that
+/// the recursive parser can't overflow the stack.
+const MAX_EXPRESSION_DEPTH: usize = 256;
+
+/// A command implemented by bunbun itself, which builtin routes refer to by
+/// name.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Builtin {
+    /// Evaluates an arithmetic expression.
+    Calc,
+    /// Generates a random UUID.
+    Uuid,
+    /// Encodes the arguments as base64.
+    Base64,
+    /// Decodes the arguments from base64.
+    Unbase64,
+    /// Percent-encodes the arguments.
+    UrlEncode,
+    /// Converts a date to a Unix timestamp, or returns the current one.
+    Epoch,
+    /// Converts a Unix timestamp to a date, or returns the current one.
+    Date,
+    /// Returns the arguments as-is.
+    Echo,
+}
+
+impl Builtin {
+    pub const NAMES: &'static [&'static str] = &[
+        "calc",
+        "uuid",
+        "b64",
+        "unb64",
+        "urlencode",
+        "epoch",
+        "date",
+        "echo",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "calc" => Self::Calc,
+            "uuid" => Self::Uuid,
+            "b64" => Self::Base64,
+            "unb64" => Self::Unbase64,
+            "urlencode" => Self::UrlEncode,
+            "epoch" => Self::Epoch,
+            "date" => Self::Date,
+            "echo" => Self::Echo,
+            _ => return None,
+        })
+    }
+
+    /// Runs the command with the provided arguments, returning the text to
+    /// respond with. Returns an error describing the problem if the arguments
+    /// were invalid.
+    pub fn run(self, args: &str) -> Result<String, String> {
+        match self {
+            Self::Calc => calc(args).map(|result| result.to_string()),
+            Self::Uuid => Ok(uuid::Uuid::new_v4().to_string()),
+            Self::Base64 => Ok(STANDARD.encode(args)),
+            Self::Unbase64 => {
+                let bytes = STANDARD
+                    .decode(args)
+                    .map_err(|e| format!("{args:?} isn't valid base64: {e}"))?;
+                String::from_utf8(bytes).map_err(|_| String::from("The decoded bytes aren't text"))
+            }
+            Self::UrlEncode => Ok(utf8_percent_encode(args, NON_ALPHANUMERIC).to_string()),
+            Self::Epoch => epoch(args).map(|timestamp| timestamp.to_string()),
+            Self::Date => date(args),
+            Self::Echo => Ok(args.to_owned()),
+        }
+    }
+}
+
+/// Returns the Unix timestamp of the date, which is either an RFC 3339
+/// timestamp or a calendar date at midnight UTC. Returns the current timestamp
+/// if no date was provided.
+fn epoch(date: &str) -> Result<i64, String> {
+    if date.is_empty() {
+        return Ok(OffsetDateTime::now_utc().unix_timestamp());
+    }
+
+    OffsetDateTime::parse(date, &Rfc3339)
+        .or_else(|_| {
+            Date::parse(date, format_description!("[year]-[month]-[day]"))
+                .map(|date| date.midnight().assume_utc())
+        })
+        .map(OffsetDateTime::unix_timestamp)
+        .map_err(|_| format!("{date:?} isn't a YYYY-MM-DD date or an RFC 3339 timestamp"))
+}
+
+/// Returns the Unix timestamp as an RFC 3339 timestamp in UTC. Returns the
+/// current time if no timestamp was provided.
+fn date(timestamp: &str) -> Result<String, String> {
+    let date = if timestamp.is_empty() {
+        OffsetDateTime::now_utc()
+    } else {
+        timestamp
+            .parse()
+            .ok()
+            .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp).ok())
+            .ok_or_else(|| format!("{timestamp:?} isn't a valid Unix timestamp"))?
+    };
+    date.format(&Rfc3339).map_err(|e| e.to_string())
+}
+
+/// Evaluates an arithmetic expression of numbers, parentheses, and the `+`,
+/// `-`, `*`, `/`, `%` and `^` operators with their usual precedence.
+fn calc(expression: &str) -> Result<f64, String> {
+    if expression.len() > MAX_EXPRESSION_LEN {
+        return Err(format!(
+            "Expressions may be at most {MAX_EXPRESSION_LEN} characters long"
+        ));
+    }
+
+    let mut parser = Calc {
+        chars: expression.chars().peekable(),
+        depth: 0,
+    };
+    let result = parser.expression()?;
+    if let Some(c) = parser.next() {
+        return Err(format!("Unexpected {c:?}"));
+    }
+    if !result.is_finite() {
+        return Err(String::from("The result isn't a finite number"));
+    }
+    Ok(result)
+}
+
+/// A recursive descent parser that evaluates an expression as it's parsed.
+struct Calc<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// How many parentheses and negations enclose the current position.
+    depth: usize,
+}
+
+impl Calc<'_> {
+    /// expression = term (("+" | "-") term)*
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// term = unary (("*" | "/" | "%") unary)*
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                value /= self.unary()?;
+            } else if self.eat('%') {
+                value %= self.unary()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// unary = "-" unary | power
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            Ok(-self.nested(Self::unary)?)
+        } else {
+            self.power()
+        }
+    }
+
+    /// power = atom ("^" unary)?
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(base.powf(self.nested(Self::unary)?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// atom = number | "(" expression ")"
+    fn atom(&mut self) -> Result<f64, String> {
+        if self.eat('(') {
+            let value = self.nested(Self::expression)?;
+            if !self.eat(')') {
+                return Err(String::from("Expected a closing parenthesis"));
+            }
+            return Ok(value);
+        }
+
+        self.skip_whitespace();
+        let mut number = String::new();
+        while let Some(c) = self.chars.next_if(|&c| c.is_ascii_digit() || c == '.') {
+            number.push(c);
+        }
+        if number.is_empty() {
+            return Err(self.chars.peek().map_or_else(
+                || String::from("Expected a number"),
+                |c| format!("Expected a number, found {c:?}"),
+            ));
+        }
+        number
+            .parse()
+            .map_err(|_| format!("{number:?} isn't a number"))
+    }
+
+    /// Parses with the provided rule one level deeper, failing if that's too
+    /// deep.
+    fn nested(&mut self, rule: fn(&mut Self) -> Result<f64, String>) -> Result<f64, String> {
+        if self.depth >= MAX_EXPRESSION_DEPTH {
+            return Err(String::from("Expression is nested too deeply"));
+        }
+        self.depth += 1;
+        let value = rule(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// Consumes the next non-whitespace character if it's the provided one.
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    /// Returns the next non-whitespace character.
+    fn next(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(char::is_ascii_whitespace).is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod run {
+    use super::*;
+
+    #[test]
+    fn names_are_known() {
+        for name in Builtin::NAMES {
+            assert!(Builtin::from_name(name).is_some(), "{name}");
+        }
+        assert_eq!(Builtin::from_name("nope"), None);
+    }
+
+    #[test]
+    fn calc_precedence() {
+        assert_eq!(Builtin::Calc.run("1 + 2 * 3"), Ok(String::from("7")));
+        assert_eq!(Builtin::Calc.run("(1 + 2) * 3"), Ok(String::from("9")));
+        assert_eq!(Builtin::Calc.run("2 ^ 3 ^ 2"), Ok(String::from("512")));
+        assert_eq!(Builtin::Calc.run("-2^2"), Ok(String::from("-4")));
+        assert_eq!(Builtin::Calc.run("2^-1"), Ok(String::from("0.5")));
+        assert_eq!(Builtin::Calc.run("7 % 4 - 10 / 4"), Ok(String::from("0.5")));
+    }
+
+    #[test]
+    fn calc_invalid() {
+        assert!(Builtin::Calc.run("").is_err());
+        assert!(Builtin::Calc.run("1 +").is_err());
+        assert!(Builtin::Calc.run("(1").is_err());
+        assert!(Builtin::Calc.run("1 2").is_err());
+        assert!(Builtin::Calc.run("1..2").is_err());
+        assert!(Builtin::Calc.run("1 / 0").is_err());
+    }
+
+    #[test]
+    fn calc_nesting_is_limited() {
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(Builtin::Calc.run(&nested(200)), Ok(String::from("1")));
+        assert_eq!(
+            Builtin::Calc.run(&nested(300)),
+            Err(String::from("Expression is nested too deeply"))
+        );
+        assert_eq!(
+            Builtin::Calc.run(&format!("{}1", "-".repeat(300))),
+            Err(String::from("Expression is nested too deeply"))
+        );
+        assert_eq!(
+            Builtin::Calc.run(&format!("{}1", "1^".repeat(300))),
+            Err(String::from("Expression is nested too deeply"))
+        );
+        // Would overflow the stack if it weren't rejected for its length
+        assert!(Builtin::Calc.run(&"-".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn uuid() {
+        let uuid = Builtin::Uuid.run("").unwrap_or_default();
+        assert!(uuid::Uuid::parse_str(&uuid).is_ok_and(|uuid| uuid.get_version_num() == 4));
+        assert_ne!(Builtin::Uuid.run(""), Builtin::Uuid.run(""));
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(
+            Builtin::Base64.run("hi there"),
+            Ok(String::from("aGkgdGhlcmU="))
+        );
+        assert_eq!(
+            Builtin::Unbase64.run("aGkgdGhlcmU="),
+            Ok(String::from("hi there"))
+        );
+        assert!(Builtin::Unbase64.run("not base64").is_err());
+        assert!(Builtin::Unbase64.run("/w==").is_err());
+    }
+
+    #[test]
+    fn urlencode() {
+        assert_eq!(
+            Builtin::UrlEncode.run("a b&c"),
+            Ok(String::from("a%20b%26c"))
+        );
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(Builtin::Epoch.run("1970-01-02"), Ok(String::from("86400")));
+        assert_eq!(
+            Builtin::Epoch.run("2001-09-09T03:46:40+02:00"),
+            Ok(String::from("1000000000"))
+        );
+        assert!(Builtin::Epoch.run("yesterday").is_err());
+        assert!(Builtin::Epoch
+            .run("")
+            .is_ok_and(|now| now.parse::<i64>().is_ok_and(|now| now > 1_000_000_000)));
+    }
+
+    #[test]
+    fn date() {
+        assert_eq!(
+            Builtin::Date.run("1000000000"),
+            Ok(String::from("2001-09-09T01:46:40Z"))
+        );
+        assert!(Builtin::Date.run("soon").is_err());
+        assert!(Builtin::Date.run("").is_ok());
+    }
+
+    #[test]
+    fn echo() {
+        assert_eq!(Builtin::Echo.run("a  b"), Ok(String::from("a  b")));
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// What identifies a route, alongside everything its program was given.
+type Key = (String, String);
+
+/// Results of executable routes, keyed by the route and its input. Once full,
+/// the least recently used result is evicted to make room for another.
+#[derive(Debug)]
+pub struct ResultCache<V> {
+    capacity: usize,
+    entries: Mutex<Entries<V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Entries<V> {
+    values: HashMap<Key, Entry<V>>,
+    /// Keys ordered by when they were last used, from least recent to most.
+    recency: BTreeMap<u64, Key>,
+    /// Incremented every time an entry is used.
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct Entry<V> {
+    value: V,
+    /// When the value expires, or None if the time to live was too long to
+    /// represent, in which case it never does.
+    expires: Option<Instant>,
+    last_used: u64,
+}
+
+impl<V: Clone> ResultCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries {
+                values: HashMap::new(),
+                recency: BTreeMap::new(),
+                clock: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached result of the route for the provided input, if it
+    /// hasn't expired yet.
+    pub fn get(&self, route: &str, input: &str) -> Option<V> {
+        let key = (route.to_owned(), input.to_owned());
+        let value = self.lock().get(&key, Instant::now());
+
+        let (hits, misses) = if value.is_some() {
+            (
+                self.hits.fetch_add(1, Ordering::Relaxed) + 1,
+                self.misses.load(Ordering::Relaxed),
+            )
+        } else {
+            (
+                self.hits.load(Ordering::Relaxed),
+                self.misses.fetch_add(1, Ordering::Relaxed) + 1,
+            )
+        };
+        debug!(
+            "Cache {} for {route} ({hits} hits, {misses} misses)",
+            if value.is_some() { "hit" } else { "miss" }
+        );
+        value
+    }
+
+    /// Caches the result of the route for the provided input, until the
+    /// provided time to live has passed.
+    pub fn insert(&self, route: &str, input: &str, value: V, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = (route.to_owned(), input.to_owned());
+        self.lock()
+            .insert(key, value, Instant::now().checked_add(ttl), self.capacity);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries<V>> {
+        // Entries are always left consistent, so a panic elsewhere doesn't
+        // invalidate them
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<V: Clone> Entries<V> {
+    fn get(&mut self, key: &Key, now: Instant) -> Option<V> {
+        let entry = self.values.get(key)?;
+        if entry.expires.is_some_and(|expires| expires <= now) {
+            self.remove(key);
+            return None;
+        }
+
+        let value = entry.value.clone();
+        let last_used = entry.last_used;
+        self.recency.remove(&last_used);
+        let used = self.tick();
+        self.recency.insert(used, key.clone());
+        if let Some(entry) = self.values.get_mut(key) {
+            entry.last_used = used;
+        }
+        Some(value)
+    }
+
+    /// Inserts the value, evicting the least recently used values until at
+    /// most the provided number remain.
+    fn insert(&mut self, key: Key, value: V, expires: Option<Instant>, capacity: usize) {
+        self.remove(&key);
+        let last_used = self.tick();
+        self.recency.insert(last_used, key.clone());
+        self.values.insert(
+            key,
+            Entry {
+                value,
+                expires,
+                last_used,
+            },
+        );
+
+        while self.values.len() > capacity {
+            if let Some((_, key)) = self.recency.pop_first() {
+                self.values.remove(&key);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.values.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+
+    const fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+#[cfg(test)]
+mod result_cache {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn cached_results_are_returned() {
+        let cache = ResultCache::new(2);
+        assert_eq!(cache.get("/a", "x"), None);
+        cache.insert("/a", "x", 1, TTL);
+        assert_eq!(cache.get("/a", "x"), Some(1));
+        assert_eq!(cache.get("/a", "y"), None);
+        assert_eq!(cache.get("/b", "x"), None);
+        assert_eq!(cache.hits.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.misses.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn expired_results_are_dropped() {
+        let cache = ResultCache::new(2);
+        cache.insert("/a", "x", 1, Duration::ZERO);
+        assert_eq!(cache.get("/a", "x"), None);
+        assert!(cache.lock().values.is_empty());
+    }
+
+    #[test]
+    fn huge_ttl_never_expires() {
+        let cache = ResultCache::new(2);
+        cache.insert("/a", "x", 1, Duration::MAX);
+        assert_eq!(cache.get("/a", "x"), Some(1));
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let cache = ResultCache::new(2);
+        cache.insert("/a", "", 1, TTL);
+        cache.insert("/b", "", 2, TTL);
+        // Using /a makes /b the least recently used
+        assert_eq!(cache.get("/a", ""), Some(1));
+        cache.insert("/c", "", 3, TTL);
+        assert_eq!(cache.get("/a", ""), Some(1));
+        assert_eq!(cache.get("/b", ""), None);
+        assert_eq!(cache.get("/c", ""), Some(3));
+    }
+
+    #[test]
+    fn reinserting_replaces_result() {
+        let cache = ResultCache::new(1);
+        cache.insert("/a", "", 1, TTL);
+        cache.insert("/a", "", 2, TTL);
+        assert_eq!(cache.get("/a", ""), Some(2));
+        assert_eq!(cache.lock().recency.len(), 1);
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let cache = ResultCache::new(0);
+        cache.insert("/a", "", 1, TTL);
+        assert_eq!(cache.get("/a", ""), None);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::executor::ExecLimits;
+use crate::BunBunError;
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Method, Request};
+use tokio::time::timeout;
+
+/// Sends hops to the endpoints of http routes, reusing connections across
+/// hops. Only plain HTTP is supported, as endpoints are expected to be
+/// internal services.
+#[derive(Debug, Clone, Default)]
+pub struct HttpClient(Client<HttpConnector>);
+
+impl HttpClient {
+    /// POSTs the JSON body and headers to the endpoint, returning the body of
+    /// its response. Time spent connecting counts towards the timeout. Fails
+    /// if the endpoint couldn't be reached, didn't respond with a successful
+    /// status, or responded with too large a body.
+    pub async fn post(
+        &self,
+        url: &str,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+        limits: ExecLimits,
+    ) -> Result<Vec<u8>, BunBunError> {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(CONTENT_TYPE, "application/json");
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        let request = request
+            .body(Body::from(body))
+            .map_err(|e| BunBunError::CustomProgram(format!("Invalid request to {url}: {e}")))?;
+
+        let run = async {
+            let response = self.0.request(request).await?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(BunBunError::UpstreamStatus(status.as_u16()));
+            }
+            read_body(response.into_body(), limits.max_output_bytes).await
+        };
+
+        timeout(limits.timeout, run)
+            .await
+            .map_err(|_| BunBunError::ProgramTimedOut(limits.timeout))?
+    }
+}
+
+/// Reads the body, failing as soon as it's larger than the provided number of
+/// bytes.
+async fn read_body(mut body: Body, max_bytes: usize) -> Result<Vec<u8>, BunBunError> {
+    let mut output = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if output.len() + chunk.len() > max_bytes {
+            return Err(BunBunError::ProgramOutputTooLarge(max_bytes));
+        }
+        output.extend_from_slice(&chunk);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod post {
+    use super::*;
+    use crate::test_utils::limits;
+    use anyhow::Result;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use std::time::Duration;
+
+    /// Serves the router on a free local port, returning its address.
+    fn serve(router: Router) -> Result<String> {
+        let server =
+            axum::Server::try_bind(&"127.0.0.1:0".parse()?)?.serve(router.into_make_service());
+        let url = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+        Ok(url)
+    }
+
+    #[tokio::test]
+    async fn headers_and_body_are_sent() -> Result<()> {
+        let url = serve(Router::new().route(
+            "/",
+            post(|headers: HeaderMap, body: String| async move {
+                format!("{:?} {body}", headers["x-user"])
+            }),
+        ))?;
+        let headers = vec![(String::from("x-user"), String::from("alice"))];
+        let response = HttpClient::default()
+            .post(&url, headers, b"{}".to_vec(), limits())
+            .await?;
+        assert_eq!(response, br#""alice" {}"#);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn error_status_is_err() -> Result<()> {
+        let url =
+            serve(Router::new().route("/", post(|| async { StatusCode::SERVICE_UNAVAILABLE })))?;
+        assert!(matches!(
+            HttpClient::default()
+                .post(&url, Vec::new(), Vec::new(), limits())
+                .await,
+            Err(BunBunError::UpstreamStatus(503))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn large_response_is_rejected() -> Result<()> {
+        let url = serve(Router::new().route("/", post(|| async { "a".repeat(2048) })))?;
+        assert!(matches!(
+            HttpClient::default()
+                .post(&url, Vec::new(), Vec::new(), limits())
+                .await,
+            Err(BunBunError::ProgramOutputTooLarge(1024))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn slow_endpoint_times_out() -> Result<()> {
+        let url = serve(Router::new().route(
+            "/",
+            post(|| async { tokio::time::sleep(Duration::from_secs(10)).await }),
+        ))?;
+        let limits = ExecLimits {
+            timeout: Duration::from_millis(100),
+            ..limits()
+        };
+        assert!(matches!(
+            HttpClient::default()
+                .post(&url, Vec::new(), Vec::new(), limits)
+                .await,
+            Err(BunBunError::ProgramTimedOut(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn https_is_err() -> Result<()> {
+        let url = serve(Router::new().route("/", post(|| async { "{}" })))?;
+        let url = url.replacen("http://", "https://", 1);
+        // The endpoint is listening, so only the scheme can be at fault
+        assert!(matches!(
+            HttpClient::default()
+                .post(&url, Vec::new(), Vec::new(), limits())
+                .await,
+            Err(BunBunError::Http(e)) if e.is_connect()
+        ));
+        Ok(())
+    }
+}
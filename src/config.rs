@@ -1,14 +1,20 @@
+use crate::argv::ArgsMode;
+use crate::builtin::Builtin;
+use crate::encoding::Encoding;
+use crate::script::Script;
 use crate::BunBunError;
 use dirs::{config_dir, home_dir};
+use regex::Regex;
 use serde::{
     de::{self, Deserializer, MapAccess, Unexpected, Visitor},
-    Deserialize, Serialize,
+    Deserialize, Serialize, Serializer,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
 use tracing::{debug, info, trace};
 
 const CONFIG_FILENAME: &str = "bunbun.yaml";
@@ -17,15 +23,157 @@ const DEFAULT_CONFIG: &[u8] = include_bytes!("../bunbun.default.yaml");
 const LARGE_FILE_SIZE_THRESHOLD: u64 = 100_000_000;
 #[cfg(test)]
 const LARGE_FILE_SIZE_THRESHOLD: u64 = 1_000_000;
+/// Names that are always provided to route templates, and thus can't be used
+/// as parameter names.
+const RESERVED_TEMPLATE_NAMES: &[&str] = &["query", "args", "argc", "captures", "raw", "encoding"];
 
 #[derive(Deserialize, Debug, PartialEq, Eq)]
 pub struct Config {
     pub bind_address: String,
     pub public_address: String,
-    pub default_route: Option<String>,
+    /// Keywords of the routes to try, in order, if no other route matched.
+    #[serde(
+        rename = "default_route",
+        default,
+        deserialize_with = "deserialize_default_routes"
+    )]
+    pub default_routes: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<PatternRoute>,
+    pub bang_sigil: Option<String>,
+    #[serde(default)]
+    pub executables: ExecutableSettings,
     pub groups: Vec<RouteGroup>,
 }
 
+/// Settings for every executable route. Routes may override the limits with
+/// their own.
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutableSettings {
+    /// How long an executable may run before it's killed.
+    pub timeout_ms: u64,
+    /// How much an executable may write to stdout or stderr before it's
+    /// killed.
+    pub max_output_bytes: usize,
+    /// How many executables may run at once, which must be at least one.
+    #[serde(deserialize_with = "deserialize_nonzero")]
+    pub max_concurrent: usize,
+    /// How many hops may wait for an executable to finish before further hops
+    /// are turned away.
+    pub max_queued: usize,
+    /// How many results of routes with a `cache_ttl` are kept.
+    pub cache_capacity: usize,
+    /// Roughly how many instructions a wasm route may execute per hop.
+    pub wasm_fuel: u64,
+    /// How large the memory of a wasm route may grow.
+    pub wasm_max_memory_bytes: usize,
+    /// How many operations a script route may perform per hop.
+    pub script_max_operations: u64,
+}
+
+impl Default for ExecutableSettings {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 10_000,
+            max_output_bytes: 1024 * 1024,
+            max_concurrent: 8,
+            max_queued: 64,
+            cache_capacity: 256,
+            wasm_fuel: 100_000_000,
+            wasm_max_memory_bytes: 64 * 1024 * 1024,
+            script_max_operations: 1_000_000,
+        }
+    }
+}
+
+/// Restrictions on the programs run by a route, which are applied before the
+/// program starts. Besides the environment and working directory, these are
+/// only supported on Linux.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sandbox {
+    /// If set, the environment is cleared except for these variables.
+    pub env: Option<Vec<String>>,
+    /// The directory to run in, instead of bunbun's working directory.
+    pub working_dir: Option<PathBuf>,
+    /// How many seconds of CPU time may be used.
+    pub cpu_secs: Option<u64>,
+    /// How large the address space may grow.
+    pub memory_bytes: Option<u64>,
+    /// How large any file that's written to may grow.
+    pub file_size_bytes: Option<u64>,
+    /// How many processes the user may have, including this one.
+    pub max_processes: Option<u64>,
+    /// The user to run as, which requires a group to be set as well. Switching
+    /// users requires bunbun to run as root.
+    pub uid: Option<u32>,
+    /// The group to run as.
+    pub gid: Option<u32>,
+    /// Whether the program and its children are prevented from gaining
+    /// privileges, such as through setuid binaries.
+    pub no_new_privs: bool,
+}
+
+impl Sandbox {
+    /// Whether any restriction must be applied while starting the program,
+    /// which is only possible on Linux.
+    pub const fn has_process_limits(&self) -> bool {
+        self.cpu_secs.is_some()
+            || self.memory_bytes.is_some()
+            || self.file_size_bytes.is_some()
+            || self.max_processes.is_some()
+            || self.uid.is_some()
+            || self.gid.is_some()
+            || self.no_new_privs
+    }
+}
+
+impl Config {
+    /// Resolves the relative paths of every route referring to a local file
+    /// against the provided directory, which should be the directory of the
+    /// config file. This way, routes behave the same regardless of the working
+    /// directory bunbun was started in.
+    pub fn resolve_relative_paths(&mut self, base: &Path) {
+        let routes = self
+            .groups
+            .iter_mut()
+            .flat_map(|group| group.routes.values_mut())
+            .chain(self.patterns.iter_mut().map(|pattern| &mut pattern.route));
+        for route in routes {
+            route.resolve_relative_paths(base);
+        }
+    }
+}
+
+/// A route that is used for queries matching a regular expression, rather than
+/// for queries starting with a keyword. As it isn't reached by a keyword, it
+/// can't have aliases or sub-routes.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct PatternRoute {
+    pub pattern: Pattern,
+    pub route: Route,
+}
+
+impl<'de> Deserialize<'de> for PatternRoute {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Fields {
+            pattern: Pattern,
+            #[serde(flatten)]
+            route: Route,
+        }
+
+        let Fields { pattern, route } = Fields::deserialize(deserializer)?;
+        if !route.aliases.is_empty() || !route.routes.is_empty() {
+            return Err(de::Error::custom(
+                "pattern routes can't have aliases or sub-routes",
+            ));
+        }
+        Ok(Self { pattern, route })
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
 pub struct RouteGroup {
     pub name: String,
@@ -36,47 +184,146 @@ pub struct RouteGroup {
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[allow(clippy::struct_field_names)]
 pub struct Route {
     pub route_type: RouteType,
+    /// Whether the type was inferred from whether the path exists, in which
+    /// case it's inferred again once the path is resolved.
+    #[serde(skip)]
+    pub inferred_type: bool,
     pub path: String,
+    pub aliases: Vec<String>,
     pub hidden: bool,
     pub description: Option<String>,
     pub min_args: Option<usize>,
     pub max_args: Option<usize>,
+    pub params: Vec<Param>,
+    pub encoding: Encoding,
+    /// The program of an exec route followed by its leading arguments, which
+    /// may be templates. If set, the path is the program.
+    pub command: Option<Vec<String>>,
+    /// How the user's arguments are passed to the program of an exec route.
+    pub args_mode: ArgsMode,
+    /// Overrides [`ExecutableSettings::timeout_ms`] for this route.
+    pub timeout_ms: Option<u64>,
+    /// Overrides [`ExecutableSettings::max_output_bytes`] for this route.
+    pub max_output_bytes: Option<usize>,
+    /// Whether executables are told about the hop through their stdin and
+    /// environment.
+    pub context: bool,
+    /// How many processes a worker route keeps alive at most. Worker routes
+    /// sharing a path share their processes.
+    pub pool_size: Option<usize>,
+    /// How many seconds the results of this route's program are reused for
+    /// hops with the same arguments. Results aren't cached if this isn't set.
+    pub cache_ttl: Option<u64>,
+    /// Restrictions on the programs of exec and worker routes.
+    pub sandbox: Sandbox,
+    /// The source of a script route. If set, it's also the path.
+    pub script: Option<Script>,
+    /// Headers sent to the endpoint of an http route, whose values may be
+    /// templates.
+    pub headers: BTreeMap<String, String>,
+    /// A query to hop to instead, followed by the arguments, if the endpoint of
+    /// an http route failed to respond.
+    pub fallback: Option<String>,
+    /// The pages opened by an `open_all` route, which are templates like a
+    /// path. If set, the path is the first page.
+    pub urls: Vec<String>,
+    /// Routes that are reached by following this route's keyword with their
+    /// own. This route is used if none of them match.
+    pub routes: HashMap<String, Self>,
+}
+
+impl Route {
+    /// Binds the provided arguments to the route's parameters, in order.
+    /// Parameters without a matching argument use their default value. Returns
+    /// None if an argument was rejected by its parameter, or if a parameter
+    /// without a default value had no matching argument.
+    pub fn bind_params<'a>(&'a self, args: &[&'a str]) -> Option<Vec<(&'a str, &'a str)>> {
+        self.params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let value = match args.get(i) {
+                    Some(arg) if param.accepts(arg) => arg,
+                    Some(_) => return None,
+                    None => param.default.as_deref()?,
+                };
+                Some((param.name.as_str(), value))
+            })
+            .collect()
+    }
+
+    /// Resolves the path of this route and its sub-routes against the provided
+    /// directory, if they refer to a local file by a relative path.
+    fn resolve_relative_paths(&mut self, base: &Path) {
+        if self.inferred_type && Path::new(&self.path).is_relative() {
+            self.route_type = get_route_type(&base.join(&self.path));
+        }
+
+        if self.route_type.is_local() && Path::new(&self.path).is_relative() {
+            let path = base.join(&self.path).display().to_string();
+            trace!("Resolved {} to {path}", self.path);
+            self.path = path;
+        }
+
+        if let Some(dir) = &mut self.sandbox.working_dir {
+            if dir.is_relative() {
+                *dir = base.join(&dir);
+            }
+        }
+
+        for route in self.routes.values_mut() {
+            route.resolve_relative_paths(base);
+        }
+    }
 }
 
 impl From<String> for Route {
     fn from(s: String) -> Self {
         Self {
-            route_type: get_route_type(&s),
+            route_type: get_route_type(Path::new(&s)),
+            inferred_type: true,
             path: s,
+            aliases: Vec::new(),
             hidden: false,
             description: None,
             min_args: None,
             max_args: None,
+            params: Vec::new(),
+            encoding: Encoding::default(),
+            command: None,
+            args_mode: ArgsMode::default(),
+            timeout_ms: None,
+            max_output_bytes: None,
+            context: false,
+            pool_size: None,
+            cache_ttl: None,
+            sandbox: Sandbox::default(),
+            script: None,
+            headers: BTreeMap::new(),
+            fallback: None,
+            urls: Vec::new(),
+            routes: HashMap::new(),
         }
     }
 }
 
 impl From<&'static str> for Route {
     fn from(s: &'static str) -> Self {
-        Self {
-            route_type: get_route_type(s),
-            path: s.to_string(),
-            hidden: false,
-            description: None,
-            min_args: None,
-            max_args: None,
-        }
+        Self::from(s.to_owned())
     }
 }
 
 /// Deserialization of the route string into the enum requires us to figure out
-/// whether or not the string is valid to run as an executable or not. To
-/// determine this, we simply check if it exists on disk or assume that it's a
-/// web path. This incurs a disk check operation, but since users shouldn't be
-/// updating the config that frequently, it should be fine.
+/// whether or not the string is valid to run as an executable or not, unless
+/// the route explicitly provides its type. To determine this, we simply check
+/// if it exists on disk or assume that it's a web path. This incurs a disk
+/// check operation, but since users shouldn't be updating the config that
+/// frequently, it should be fine.
 impl<'de> Deserialize<'de> for Route {
+    #[allow(clippy::too_many_lines)]
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
@@ -84,11 +331,29 @@ impl<'de> Deserialize<'de> for Route {
         #[derive(Deserialize)]
         #[serde(field_identifier, rename_all = "snake_case")]
         enum Field {
+            #[serde(rename = "type")]
+            Type,
             Path,
+            Aliases,
             Hidden,
             Description,
             MinArgs,
             MaxArgs,
+            Params,
+            Encoding,
+            Command,
+            ArgsMode,
+            TimeoutMs,
+            MaxOutputBytes,
+            Context,
+            PoolSize,
+            CacheTtl,
+            Sandbox,
+            Script,
+            Headers,
+            Fallback,
+            Urls,
+            Routes,
         }
 
         struct RouteVisitor;
@@ -111,20 +376,49 @@ impl<'de> Deserialize<'de> for Route {
             where
                 M: MapAccess<'de>,
             {
+                let mut route_type = None;
                 let mut path = None;
+                let mut aliases = None;
                 let mut hidden = None;
                 let mut description = None;
                 let mut min_args = None;
                 let mut max_args = None;
+                let mut params = None;
+                let mut encoding = None;
+                let mut command: Option<Vec<String>> = None;
+                let mut args_mode = None;
+                let mut timeout_ms = None;
+                let mut max_output_bytes = None;
+                let mut context = None;
+                let mut pool_size = None;
+                let mut cache_ttl = None;
+                let mut sandbox: Option<Sandbox> = None;
+                let mut script: Option<Script> = None;
+                let mut headers = None;
+                let mut fallback = None;
+                let mut urls: Option<Vec<String>> = None;
+                let mut routes = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
+                        Field::Type => {
+                            if route_type.is_some() {
+                                return Err(de::Error::duplicate_field("type"));
+                            }
+                            route_type = Some(map.next_value()?);
+                        }
                         Field::Path => {
                             if path.is_some() {
                                 return Err(de::Error::duplicate_field("path"));
                             }
                             path = Some(map.next_value::<String>()?);
                         }
+                        Field::Aliases => {
+                            if aliases.is_some() {
+                                return Err(de::Error::duplicate_field("aliases"));
+                            }
+                            aliases = Some(map.next_value()?);
+                        }
                         Field::Hidden => {
                             if hidden.is_some() {
                                 return Err(de::Error::duplicate_field("hidden"));
@@ -149,6 +443,96 @@ impl<'de> Deserialize<'de> for Route {
                             }
                             max_args = Some(map.next_value()?);
                         }
+                        Field::Params => {
+                            if params.is_some() {
+                                return Err(de::Error::duplicate_field("params"));
+                            }
+                            params = Some(map.next_value::<Vec<Param>>()?);
+                        }
+                        Field::Encoding => {
+                            if encoding.is_some() {
+                                return Err(de::Error::duplicate_field("encoding"));
+                            }
+                            encoding = Some(map.next_value()?);
+                        }
+                        Field::Command => {
+                            if command.is_some() {
+                                return Err(de::Error::duplicate_field("command"));
+                            }
+                            command = Some(map.next_value()?);
+                        }
+                        Field::ArgsMode => {
+                            if args_mode.is_some() {
+                                return Err(de::Error::duplicate_field("args_mode"));
+                            }
+                            args_mode = Some(map.next_value()?);
+                        }
+                        Field::TimeoutMs => {
+                            if timeout_ms.is_some() {
+                                return Err(de::Error::duplicate_field("timeout_ms"));
+                            }
+                            timeout_ms = Some(map.next_value()?);
+                        }
+                        Field::MaxOutputBytes => {
+                            if max_output_bytes.is_some() {
+                                return Err(de::Error::duplicate_field("max_output_bytes"));
+                            }
+                            max_output_bytes = Some(map.next_value()?);
+                        }
+                        Field::Context => {
+                            if context.is_some() {
+                                return Err(de::Error::duplicate_field("context"));
+                            }
+                            context = Some(map.next_value()?);
+                        }
+                        Field::PoolSize => {
+                            if pool_size.is_some() {
+                                return Err(de::Error::duplicate_field("pool_size"));
+                            }
+                            pool_size = Some(map.next_value()?);
+                        }
+                        Field::CacheTtl => {
+                            if cache_ttl.is_some() {
+                                return Err(de::Error::duplicate_field("cache_ttl"));
+                            }
+                            cache_ttl = Some(map.next_value()?);
+                        }
+                        Field::Sandbox => {
+                            if sandbox.is_some() {
+                                return Err(de::Error::duplicate_field("sandbox"));
+                            }
+                            sandbox = Some(map.next_value()?);
+                        }
+                        Field::Script => {
+                            if script.is_some() {
+                                return Err(de::Error::duplicate_field("script"));
+                            }
+                            script = Some(map.next_value()?);
+                        }
+                        Field::Headers => {
+                            if headers.is_some() {
+                                return Err(de::Error::duplicate_field("headers"));
+                            }
+                            headers = Some(map.next_value()?);
+                        }
+                        Field::Fallback => {
+                            if fallback.is_some() {
+                                return Err(de::Error::duplicate_field("fallback"));
+                            }
+                            fallback = Some(map.next_value()?);
+                        }
+                        Field::Urls => {
+                            if urls.is_some() {
+                                return Err(de::Error::duplicate_field("urls"));
+                            }
+                            urls = Some(map.next_value()?);
+                        }
+                        Field::Routes => {
+                            if routes.is_some() {
+                                return Err(de::Error::duplicate_field("routes"));
+                            }
+                            routes = Some(map.next_value()?);
+                        }
                     }
                 }
 
@@ -165,14 +549,188 @@ impl<'de> Deserialize<'de> for Route {
                     }
                 }
 
-                let path = path.ok_or_else(|| de::Error::missing_field("path"))?;
+                let params = params.unwrap_or_default();
+                validate_params(&params)?;
+
+                if pool_size == Some(0) {
+                    return Err(de::Error::invalid_value(
+                        Unexpected::Unsigned(0),
+                        &"a pool size of at least 1",
+                    ));
+                }
+
+                if script.is_some() && (path.is_some() || command.is_some()) {
+                    return Err(de::Error::custom(
+                        "script can't be set alongside a path or command",
+                    ));
+                }
+
+                if urls.is_some() && (path.is_some() || command.is_some() || script.is_some()) {
+                    return Err(de::Error::custom(
+                        "urls can't be set alongside a path, command or script",
+                    ));
+                }
+                if urls.as_ref().is_some_and(Vec::is_empty) {
+                    return Err(de::Error::invalid_length(0, &"at least one URL"));
+                }
+
+                let path = match (path, &command) {
+                    (Some(_), Some(_)) => {
+                        return Err(de::Error::custom("path and command can't both be set"))
+                    }
+                    (None, Some(command)) => command
+                        .first()
+                        .cloned()
+                        .ok_or_else(|| de::Error::invalid_length(0, &"a program"))?,
+                    (path, None) => path
+                        .or_else(|| script.as_ref().map(Script::to_string))
+                        .or_else(|| urls.as_ref().and_then(|urls| urls.first().cloned()))
+                        .ok_or_else(|| de::Error::missing_field("path"))?,
+                };
+
+                // These only make sense for programs, so they imply an exec
+                // route unless a type was given
+                let runs_program = command.is_some()
+                    || args_mode.is_some_and(|mode| mode != ArgsMode::default())
+                    || context == Some(true)
+                    || sandbox.is_some();
+
+                // A command places the arguments itself, unless told otherwise
+                let args_mode = args_mode.unwrap_or_else(|| {
+                    if command.is_some() {
+                        ArgsMode::Omit
+                    } else {
+                        ArgsMode::Split
+                    }
+                });
+                let context = context.unwrap_or_default();
+                if context && args_mode == ArgsMode::Stdin {
+                    return Err(de::Error::custom(
+                        "context and the stdin args_mode can't both be used",
+                    ));
+                }
+
+                let inferred_type =
+                    route_type.is_none() && script.is_none() && urls.is_none() && !runs_program;
+                let route_type = route_type.unwrap_or_else(|| {
+                    if script.is_some() {
+                        RouteType::Script
+                    } else if urls.is_some() {
+                        RouteType::OpenAll
+                    } else if runs_program {
+                        RouteType::Internal
+                    } else {
+                        get_route_type(Path::new(&path))
+                    }
+                });
+                if (route_type == RouteType::Script) != script.is_some() {
+                    return Err(de::Error::custom(
+                        "script routes must have a script, and other routes can't",
+                    ));
+                }
+                if (route_type == RouteType::OpenAll) != urls.is_some() {
+                    return Err(de::Error::custom(
+                        "open_all routes must have urls, and other routes can't",
+                    ));
+                }
+                if route_type == RouteType::Builtin && Builtin::from_name(&path).is_none() {
+                    return Err(de::Error::unknown_variant(&path, Builtin::NAMES));
+                }
+                if route_type != RouteType::Http && (headers.is_some() || fallback.is_some()) {
+                    return Err(de::Error::custom(
+                        "only http routes can have headers or a fallback",
+                    ));
+                }
+                if route_type == RouteType::Http
+                    && path
+                        .get(..8)
+                        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("https://"))
+                {
+                    return Err(de::Error::custom("http routes can't call https urls"));
+                }
+                if cache_ttl.is_some()
+                    && !matches!(
+                        route_type,
+                        RouteType::Internal
+                            | RouteType::Worker
+                            | RouteType::Wasm
+                            | RouteType::Script
+                            | RouteType::Http
+                    )
+                {
+                    return Err(de::Error::custom(
+                        "only routes that run a program can have a cache_ttl",
+                    ));
+                }
+                // Modules and scripts can't be interrupted, so they're limited
+                // by how much work they do instead
+                if route_type == RouteType::Wasm && timeout_ms.is_some() {
+                    return Err(de::Error::custom(
+                        "wasm routes can't have a timeout_ms, use wasm_fuel instead",
+                    ));
+                }
+                if route_type == RouteType::Script && timeout_ms.is_some() {
+                    return Err(de::Error::custom(
+                        "script routes can't have a timeout_ms, use script_max_operations instead",
+                    ));
+                }
+                if route_type != RouteType::Worker && pool_size.is_some() {
+                    return Err(de::Error::custom("only worker routes can have a pool_size"));
+                }
+                // Other routes that run a program always receive the context
+                if route_type != RouteType::Internal && context {
+                    return Err(de::Error::custom("only exec routes can have context"));
+                }
+                if route_type != RouteType::Internal
+                    && (command.is_some() || args_mode != ArgsMode::default())
+                {
+                    return Err(de::Error::custom(
+                        "only exec routes can have a command or an args_mode",
+                    ));
+                }
+                let sandbox = sandbox.unwrap_or_default();
+                if sandbox != Sandbox::default()
+                    && !matches!(route_type, RouteType::Internal | RouteType::Worker)
+                {
+                    return Err(de::Error::custom(
+                        "only exec and worker routes can have a sandbox",
+                    ));
+                }
+                // Otherwise the program would keep bunbun's group, which is
+                // root's if bunbun switches users at all
+                if sandbox.uid.is_some() && sandbox.gid.is_none() {
+                    return Err(de::Error::custom("a sandbox's uid requires a gid"));
+                }
+                if cfg!(not(target_os = "linux")) && sandbox.has_process_limits() {
+                    return Err(de::Error::custom(
+                        "sandbox limits and user switching are only supported on Linux",
+                    ));
+                }
+
                 Ok(Route {
-                    route_type: get_route_type(&path),
+                    route_type,
+                    inferred_type,
                     path,
+                    aliases: aliases.unwrap_or_default(),
                     hidden: hidden.unwrap_or_default(),
                     description,
                     min_args,
                     max_args,
+                    params,
+                    encoding: encoding.unwrap_or_default(),
+                    command,
+                    args_mode,
+                    timeout_ms,
+                    max_output_bytes,
+                    context,
+                    pool_size,
+                    cache_ttl,
+                    sandbox,
+                    script,
+                    headers: headers.unwrap_or_default(),
+                    fallback,
+                    urls: urls.unwrap_or_default(),
+                    routes: routes.unwrap_or_default(),
                 })
             }
         }
@@ -194,27 +752,275 @@ impl std::fmt::Display for Route {
                 path,
                 ..
             } => write!(f, "file ({path})"),
+            Self {
+                route_type: RouteType::StaticFile,
+                path,
+                ..
+            } => write!(f, "static file ({path})"),
+            Self {
+                route_type: RouteType::Alias,
+                path,
+                ..
+            } => write!(f, "alias ({path})"),
+            Self {
+                route_type: RouteType::Worker,
+                path,
+                ..
+            } => write!(f, "worker ({path})"),
+            Self {
+                route_type: RouteType::Wasm,
+                path,
+                ..
+            } => write!(f, "wasm ({path})"),
+            Self {
+                route_type: RouteType::Script,
+                ..
+            } => write!(f, "script"),
+            Self {
+                route_type: RouteType::Http,
+                path,
+                ..
+            } => write!(f, "http ({path})"),
+            Self {
+                route_type: RouteType::Builtin,
+                path,
+                ..
+            } => write!(f, "builtin ({path})"),
+            Self {
+                route_type: RouteType::OpenAll,
+                urls,
+                ..
+            } => write!(f, "open all ({})", urls.join(", ")),
+        }
+    }
+}
+
+/// Checks that parameter names don't shadow the arguments bunbun provides to
+/// templates, that default values are accepted by their own parameter, and
+/// that parameters with default values come last.
+fn validate_params<E: de::Error>(params: &[Param]) -> Result<(), E> {
+    let mut seen_default = false;
+    for param in params {
+        if RESERVED_TEMPLATE_NAMES.contains(&param.name.as_str()) {
+            return Err(E::invalid_value(
+                Unexpected::Str(&param.name),
+                &"a parameter name not used by bunbun",
+            ));
+        }
+
+        match &param.default {
+            Some(default) if !param.accepts(default) => {
+                return Err(E::invalid_value(
+                    Unexpected::Str(default),
+                    &"a default value accepted by its parameter",
+                ));
+            }
+            Some(_) => seen_default = true,
+            None if seen_default => {
+                return Err(E::custom(format!(
+                    "parameter {} must have a default as it follows a parameter with one",
+                    param.name
+                )));
+            }
+            None => (),
         }
     }
+
+    Ok(())
+}
+
+/// A named positional argument of a route, which is available to the route's
+/// path under its name.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Param {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub kind: ParamType,
+    pub pattern: Option<Pattern>,
+    #[serde(default, deserialize_with = "deserialize_scalar")]
+    pub default: Option<String>,
+}
+
+impl Param {
+    /// Checks if the provided argument is acceptable for this parameter.
+    pub fn accepts(&self, arg: &str) -> bool {
+        let kind_matches = match self.kind {
+            ParamType::String => true,
+            ParamType::Int => arg.parse::<i64>().is_ok(),
+        };
+        kind_matches && self.pattern.as_ref().is_none_or(|p| p.is_match(arg))
+    }
 }
 
-/// Classifies the path depending on if the there exists a local file.
-fn get_route_type(path: &str) -> RouteType {
-    if std::path::Path::new(path).exists() {
-        debug!("Parsed {path} as a valid local path.");
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamType {
+    #[default]
+    String,
+    Int,
+}
+
+/// A regular expression that must match an argument in its entirety.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn is_match(&self, s: &str) -> bool {
+        self.regex.is_match(s)
+    }
+
+    /// Returns the groups captured when matching the provided string, keyed by
+    /// both their index and their name, if they have one. The entire match is
+    /// available as group `0`. Returns None if the string didn't match.
+    pub fn captures(&self, s: &str) -> Option<HashMap<String, String>> {
+        let captures = self.regex.captures(s)?;
+        let mut groups = HashMap::new();
+        for (i, name) in self.regex.capture_names().enumerate() {
+            if let Some(group) = captures.get(i) {
+                groups.insert(i.to_string(), group.as_str().to_owned());
+                if let Some(name) = name {
+                    groups.insert(name.to_owned(), group.as_str().to_owned());
+                }
+            }
+        }
+        Some(groups)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Pattern {}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        let regex = Regex::new(&format!("^(?:{source})$")).map_err(de::Error::custom)?;
+        Ok(Self { source, regex })
+    }
+}
+
+/// Deserializes any scalar value into its string representation, so that
+/// values such as `default: 1` don't need to be quoted.
+fn deserialize_scalar<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Scalar {
+        String(String),
+        Int(i64),
+        Float(f64),
+        Bool(bool),
+    }
+
+    Ok(Some(match Scalar::deserialize(deserializer)? {
+        Scalar::String(s) => s,
+        Scalar::Int(i) => i.to_string(),
+        Scalar::Float(f) => f.to_string(),
+        Scalar::Bool(b) => b.to_string(),
+    }))
+}
+
+/// Deserializes a count that must be at least one.
+fn deserialize_nonzero<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    NonZeroUsize::deserialize(deserializer).map(NonZeroUsize::get)
+}
+
+/// Deserializes either a single keyword or a list of keywords, so that configs
+/// predating fallback chains are still valid.
+fn deserialize_default_routes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(OneOrMany::One(keyword)) => vec![keyword],
+        Some(OneOrMany::Many(keywords)) => keywords,
+    })
+}
+
+/// Classifies the path depending on if the there exists a local file. This is
+/// only used for routes that don't specify their type, as the result depends on
+/// the working directory. Relative paths are classified again once they're
+/// resolved against the directory of the config file.
+fn get_route_type(path: &Path) -> RouteType {
+    if path.exists() {
+        debug!("Parsed {} as a valid local path.", path.display());
         RouteType::Internal
     } else {
-        debug!("{path} does not exist on disk, assuming web path.");
+        debug!(
+            "{} does not exist on disk, assuming web path.",
+            path.display()
+        );
         RouteType::External
     }
 }
 
-/// There exists two route types: an external path (e.g. a URL) or an internal
-/// path (to a file).
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+/// How a route's path is interpreted. Routes may set this explicitly with the
+/// `type` field, otherwise it's inferred with [`get_route_type`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "snake_case"))]
 pub enum RouteType {
+    /// A path (e.g. a URL) to redirect to.
+    #[serde(rename(deserialize = "redirect"))]
     External,
+    /// A path to an executable, whose output determines the response.
+    #[serde(rename(deserialize = "exec"))]
     Internal,
+    /// A path to a file, whose contents are the response.
+    StaticFile,
+    /// A query to hop to instead, which is followed by the arguments.
+    Alias,
+    /// A path to a long-lived executable, which is sent each hop as a line of
+    /// JSON and responds in kind.
+    Worker,
+    /// A path to a WebAssembly module, which is called with each hop.
+    Wasm,
+    /// An inline script, which is run with each hop.
+    Script,
+    /// A URL of an HTTP endpoint, which is sent each hop as JSON and responds
+    /// in kind.
+    Http,
+    /// The name of a command implemented by bunbun itself.
+    Builtin,
+    /// Several paths to open at once, which are set by the route's URLs.
+    OpenAll,
+}
+
+impl RouteType {
+    /// Whether the path of this route type refers to a local file.
+    pub const fn is_local(self) -> bool {
+        matches!(
+            self,
+            Self::Internal | Self::StaticFile | Self::Worker | Self::Wasm
+        )
+    }
 }
 
 pub struct FileData {
@@ -241,9 +1047,9 @@ pub fn get_config_data() -> Result<FileData, BunBunError> {
             folders.push(folder);
         }
 
-        folders
-            .iter_mut()
-            .for_each(|folder| folder.push(CONFIG_FILENAME));
+        for folder in &mut folders {
+            folder.push(CONFIG_FILENAME);
+        }
 
         folders
     };
@@ -341,7 +1147,7 @@ mod route {
         let tmpfile = NamedTempFile::new_in(".")?;
         let path = tmpfile.path().display().to_string();
         let path = path
-            .get(path.rfind(".").context("While finding .")?..)
+            .get(path.rfind('.').context("While finding .")?..)
             .context("While getting the path")?;
         let path = Path::new(path);
         assert!(path.is_relative());
@@ -378,14 +1184,562 @@ mod route {
         Ok(())
     }
 
+    #[test]
+    fn deserialize_aliases() -> Result<()> {
+        let route = from_str::<Route>("path: /ls\naliases: [list, help]")?;
+        assert_eq!(route.aliases, ["list", "help"]);
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_duplicate_aliases_field_fails() {
+        assert!(from_str::<Route>("path: /ls\naliases: [list]\naliases: [help]").is_err());
+    }
+
+    #[test]
+    fn deserialize_params() -> Result<()> {
+        let route = from_str::<Route>(
+            r#"
+            path: "https://jira/browse/{{ticket}}"
+            params:
+              - name: ticket
+                pattern: "[A-Z]+-\\d+"
+              - name: n
+                type: int
+                default: 1
+            "#,
+        )?;
+        assert_eq!(route.params.len(), 2);
+        assert_eq!(route.params[0].kind, ParamType::String);
+        assert_eq!(route.params[1].kind, ParamType::Int);
+        assert_eq!(route.params[1].default.as_deref(), Some("1"));
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_invalid_params_fails() {
+        // Invalid regex
+        assert!(from_str::<Route>("path: a\nparams: [{name: a, pattern: '('}]").is_err());
+        // Default that doesn't pass its own validation
+        assert!(from_str::<Route>("path: a\nparams: [{name: a, type: int, default: b}]").is_err());
+        // Required parameter after an optional one
+        assert!(from_str::<Route>("path: a\nparams: [{name: a, default: b}, {name: c}]").is_err());
+        // Reserved names
+        assert!(from_str::<Route>("path: a\nparams: [{name: query}]").is_err());
+        // Unknown fields
+        assert!(from_str::<Route>("path: a\nparams: [{name: a, foo: b}]").is_err());
+    }
+
+    #[test]
+    fn deserialize_encoding() -> Result<()> {
+        assert_eq!(from_str::<Route>("path: a")?.encoding, Encoding::Fragment);
+        assert_eq!(
+            from_str::<Route>("path: a\nencoding: path_segment")?.encoding,
+            Encoding::PathSegment
+        );
+        assert!(from_str::<Route>("path: a\nencoding: base64").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_executable_limits() -> Result<()> {
+        let route = from_str::<Route>(
+            "path: a\ntype: exec\ntimeout_ms: 500\nmax_output_bytes: 64\ncache_ttl: 300",
+        )?;
+        assert_eq!(route.timeout_ms, Some(500));
+        assert_eq!(route.max_output_bytes, Some(64));
+        assert_eq!(route.cache_ttl, Some(300));
+        assert!(from_str::<Route>("path: a\ntimeout_ms: -1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_has_no_timeout() {
+        assert!(from_str::<Route>("path: a\ntype: wasm\nmax_output_bytes: 64").is_ok());
+        assert!(from_str::<Route>("path: a\ntype: wasm\ntimeout_ms: 500").is_err());
+    }
+
+    #[test]
+    fn script_has_no_timeout() {
+        assert!(from_str::<Route>("script: '\"a\"'\nmax_output_bytes: 64").is_ok());
+        assert!(from_str::<Route>("script: '\"a\"'\ntimeout_ms: 500").is_err());
+    }
+
+    #[test]
+    fn cache_ttl_requires_a_program() {
+        for route_type in ["exec", "worker", "wasm", "http"] {
+            let route = format!("path: a\ntype: {route_type}\ncache_ttl: 60");
+            assert!(from_str::<Route>(&route).is_ok(), "{route_type}");
+        }
+        for route_type in ["redirect", "static_file", "alias", "builtin"] {
+            let route = format!("path: calc\ntype: {route_type}\ncache_ttl: 60");
+            assert!(from_str::<Route>(&route).is_err(), "{route_type}");
+        }
+        assert!(from_str::<Route>("urls: [a]\ncache_ttl: 60").is_err());
+        assert!(from_str::<Route>("script: '\"a\"'\ncache_ttl: 60").is_ok());
+    }
+
+    #[test]
+    fn deserialize_command() -> Result<()> {
+        let route = from_str::<Route>("command: [/bin/sh, run.sh, \"{{query}}\"]")?;
+        assert_eq!(route.path, "/bin/sh");
+        assert_eq!(route.route_type, RouteType::Internal);
+        assert_eq!(route.args_mode, ArgsMode::Omit);
+
+        let route = from_str::<Route>("command: [/bin/sh, run.sh]\nargs_mode: shell_words")?;
+        assert_eq!(route.args_mode, ArgsMode::ShellWords);
+        assert_eq!(from_str::<Route>("path: a")?.args_mode, ArgsMode::Split);
+
+        assert!(from_str::<Route>("command: []").is_err());
+        assert!(from_str::<Route>("path: a\ncommand: [b]").is_err());
+        assert!(from_str::<Route>("path: a\nargs_mode: stdin\ncontext: true").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn context_requires_an_exec_route() -> Result<()> {
+        assert!(from_str::<Route>("path: a\ncontext: true")?.context);
+        assert!(from_str::<Route>("type: redirect\npath: a\ncontext: false").is_ok());
+        for route_type in [
+            "redirect",
+            "static_file",
+            "alias",
+            "builtin",
+            "worker",
+            "http",
+        ] {
+            let route = format!("path: calc\ntype: {route_type}\ncontext: true");
+            assert!(from_str::<Route>(&route).is_err(), "{route_type}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn command_requires_an_exec_route() -> Result<()> {
+        assert_eq!(
+            from_str::<Route>("command: [./missing.sh]")?.route_type,
+            RouteType::Internal
+        );
+        assert_eq!(
+            from_str::<Route>("path: a\nargs_mode: stdin")?.route_type,
+            RouteType::Internal
+        );
+        assert!(from_str::<Route>("type: redirect\npath: a\nargs_mode: split").is_ok());
+        assert!(from_str::<Route>("type: redirect\npath: a\nargs_mode: single").is_err());
+        assert!(from_str::<Route>("type: worker\ncommand: [a, b]").is_err());
+        assert!(from_str::<Route>("type: http\npath: a\nargs_mode: stdin").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_script() -> Result<()> {
+        let route = from_str::<Route>("script: '`https://example.com/${query}`'")?;
+        assert_eq!(route.path, "`https://example.com/${query}`");
+        assert_eq!(route.route_type, RouteType::Script);
+        assert!(route.script.is_some());
+
+        // Syntax error
+        assert!(from_str::<Route>("script: 'let = 1'").is_err());
+        // Scripts are neither paths nor commands
+        assert!(from_str::<Route>("path: a\nscript: query").is_err());
+        assert!(from_str::<Route>("command: [a]\nscript: query").is_err());
+        assert!(from_str::<Route>("type: script\npath: query").is_err());
+        assert!(from_str::<Route>("type: redirect\nscript: query").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_http() -> Result<()> {
+        let route = from_str::<Route>(
+            r#"
+            type: http
+            path: "http://127.0.0.1:8000/?q={{query}}"
+            headers:
+              x-query: "{{query}}"
+            fallback: g
+            "#,
+        )?;
+        assert_eq!(route.route_type, RouteType::Http);
+        assert_eq!(route.headers["x-query"], "{{query}}");
+        assert_eq!(route.fallback.as_deref(), Some("g"));
+
+        assert!(from_str::<Route>("path: a\nfallback: g").is_err());
+        assert!(from_str::<Route>("path: a\nheaders: {x-a: b}").is_err());
+        assert!(from_str::<Route>("type: http\npath: https://127.0.0.1").is_err());
+        assert!(from_str::<Route>("type: http\npath: HTTPS://127.0.0.1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_builtin() -> Result<()> {
+        let route = from_str::<Route>("type: builtin\npath: calc")?;
+        assert_eq!(route.route_type, RouteType::Builtin);
+        assert!(from_str::<Route>("type: builtin\npath: nope").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_open_all() -> Result<()> {
+        let route = from_str::<Route>("urls: [https://a.com, https://b.com]")?;
+        assert_eq!(route.route_type, RouteType::OpenAll);
+        assert_eq!(route.path, "https://a.com");
+        assert_eq!(route.urls.len(), 2);
+
+        assert!(from_str::<Route>("urls: []").is_err());
+        assert!(from_str::<Route>("path: a\nurls: [b]").is_err());
+        assert!(from_str::<Route>("type: open_all\npath: a").is_err());
+        assert!(from_str::<Route>("type: redirect\nurls: [b]").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_sandbox() -> Result<()> {
+        let route = from_str::<Route>(
+            "
+            path: a
+            sandbox:
+              env: [PATH]
+              memory_bytes: 1024
+              no_new_privs: true
+            ",
+        )?;
+        assert_eq!(
+            route.sandbox,
+            Sandbox {
+                env: Some(vec![String::from("PATH")]),
+                memory_bytes: Some(1024),
+                no_new_privs: true,
+                ..Sandbox::default()
+            }
+        );
+        assert!(route.sandbox.has_process_limits());
+        assert!(!Sandbox::default().has_process_limits());
+        assert!(from_str::<Route>("path: a\nsandbox: {cpu: 1}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pool_size_requires_a_worker() -> Result<()> {
+        assert_eq!(
+            from_str::<Route>("type: worker\npath: a\npool_size: 2")?.pool_size,
+            Some(2)
+        );
+        assert!(from_str::<Route>("type: worker\npath: a\npool_size: 0").is_err());
+        assert!(from_str::<Route>("type: exec\npath: a\npool_size: 2").is_err());
+        assert!(from_str::<Route>("path: https://example.com\npool_size: 2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sandbox_requires_a_program() -> Result<()> {
+        assert_eq!(
+            from_str::<Route>("path: a\nsandbox: {env: []}")?.route_type,
+            RouteType::Internal
+        );
+        assert!(from_str::<Route>("type: worker\npath: a\nsandbox: {env: []}").is_ok());
+        assert!(from_str::<Route>("type: redirect\npath: a\nsandbox: {env: []}").is_err());
+        assert!(from_str::<Route>("type: wasm\npath: a\nsandbox: {env: []}").is_err());
+        assert!(from_str::<Route>("type: http\npath: a\nsandbox: {env: []}").is_err());
+        assert!(from_str::<Route>("script: '\"a\"'\nsandbox: {env: []}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sandbox_uid_requires_gid() -> Result<()> {
+        let route = from_str::<Route>("path: a\nsandbox: {uid: 1000, gid: 1000}")?;
+        assert_eq!(route.sandbox.uid, Some(1000));
+        assert!(from_str::<Route>("path: a\nsandbox: {uid: 1000}").is_err());
+        assert!(from_str::<Route>("path: a\nsandbox: {gid: 1000}").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_sub_routes() -> Result<()> {
+        let route = from_str::<Route>(
+            r#"
+            path: "https://github.com/search?q={{query}}"
+            routes:
+              pr: "https://github.com/pulls"
+              repo:
+                path: "https://github.com/{{query}}"
+                routes:
+                  issues: "https://github.com/{{query}}/issues"
+            "#,
+        )?;
+        assert_eq!(route.routes["pr"], Route::from("https://github.com/pulls"));
+        assert_eq!(
+            route.routes["repo"].routes["issues"],
+            Route::from("https://github.com/{{query}}/issues")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_explicit_type() -> Result<()> {
+        // Explicit types win over inference, even if the file doesn't exist
+        assert_eq!(
+            from_str::<Route>("path: ./missing.sh\ntype: exec")?.route_type,
+            RouteType::Internal
+        );
+        assert_eq!(
+            from_str::<Route>("path: /bin/sh\ntype: redirect")?.route_type,
+            RouteType::External
+        );
+        assert_eq!(
+            from_str::<Route>("path: a.html\ntype: static_file")?.route_type,
+            RouteType::StaticFile
+        );
+        assert_eq!(
+            from_str::<Route>("path: g\ntype: alias")?.route_type,
+            RouteType::Alias
+        );
+        assert_eq!(
+            from_str::<Route>("path: ./worker.py\ntype: worker")?.route_type,
+            RouteType::Worker
+        );
+        assert_eq!(
+            from_str::<Route>("path: ./plugin.wasm\ntype: wasm")?.route_type,
+            RouteType::Wasm
+        );
+        assert!(from_str::<Route>("path: a\ntype: url").is_err());
+        assert!(from_str::<Route>("path: a\ntype: exec\ntype: exec").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_relative_paths() -> Result<()> {
+        let mut route = from_str::<Route>(
+            "
+            path: run.sh
+            type: exec
+            sandbox: {working_dir: tmp}
+            routes:
+              abs: {path: /bin/sh, type: exec}
+              page: {path: page.html, type: static_file}
+              url: {path: https://example.com, type: redirect}
+              alias: {path: g, type: alias}
+            ",
+        )?;
+        route.resolve_relative_paths(Path::new("/etc/bunbun"));
+        assert_eq!(route.path, "/etc/bunbun/run.sh");
+        assert_eq!(
+            route.sandbox.working_dir.as_deref(),
+            Some(Path::new("/etc/bunbun/tmp"))
+        );
+        assert_eq!(route.routes["abs"].path, "/bin/sh");
+        assert_eq!(route.routes["page"].path, "/etc/bunbun/page.html");
+        assert_eq!(route.routes["url"].path, "https://example.com");
+        assert_eq!(route.routes["alias"].path, "g");
+        Ok(())
+    }
+
+    #[test]
+    fn inferred_types_use_resolved_paths() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        std::fs::write(dir.path().join("run.sh"), "")?;
+
+        let mut route = from_str::<Route>("run.sh")?;
+        assert_eq!(route.route_type, RouteType::External);
+        route.resolve_relative_paths(dir.path());
+        assert_eq!(route.route_type, RouteType::Internal);
+        assert_eq!(Path::new(&route.path), dir.path().join("run.sh"));
+
+        // Exists relative to the working directory, but not the config's
+        let mut route = from_str::<Route>("Cargo.toml")?;
+        assert_eq!(route.route_type, RouteType::Internal);
+        route.resolve_relative_paths(dir.path());
+        assert_eq!(route.route_type, RouteType::External);
+        assert_eq!(route.path, "Cargo.toml");
+
+        let mut route = from_str::<Route>("path: run.sh\ntype: redirect")?;
+        route.resolve_relative_paths(dir.path());
+        assert_eq!(route.route_type, RouteType::External);
+        Ok(())
+    }
+
     #[test]
     fn serialize() -> Result<()> {
         assert_eq!(
             &to_string(&Route::from("hello world"))?,
-            "---\nroute_type: External\npath: hello world\nhidden: false\ndescription: ~\nmin_args: ~\nmax_args: ~\n"
+            "route_type: External\npath: hello world\naliases: []\nhidden: false\ndescription: null\nmin_args: null\nmax_args: null\nparams: []\nencoding: fragment\ncommand: null\nargs_mode: split\ntimeout_ms: null\nmax_output_bytes: null\ncontext: false\npool_size: null\ncache_ttl: null\nsandbox:\n  env: null\n  working_dir: null\n  cpu_secs: null\n  memory_bytes: null\n  file_size_bytes: null\n  max_processes: null\n  uid: null\n  gid: null\n  no_new_privs: false\nscript: null\nheaders: {}\nfallback: null\nurls: []\nroutes: {}\n"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod pattern {
+    use super::*;
+    use anyhow::Result;
+    use serde_yaml::from_str;
+
+    #[test]
+    fn must_match_entirely() -> Result<()> {
+        let pattern = from_str::<Pattern>(r"'CVE-\d+-\d+'")?;
+        assert!(pattern.is_match("CVE-2024-1234"));
+        assert!(!pattern.is_match("see CVE-2024-1234"));
+        assert!(!pattern.is_match("CVE-2024-1234 please"));
+        Ok(())
+    }
+
+    #[test]
+    fn captures_are_keyed_by_index_and_name() -> Result<()> {
+        let pattern = from_str::<Pattern>(r"'(?P<project>[A-Z]+)-(\d+)'")?;
+        let captures = pattern.captures("PROJ-123").expect("pattern to match");
+        assert_eq!(captures["0"], "PROJ-123");
+        assert_eq!(captures["1"], "PROJ");
+        assert_eq!(captures["project"], "PROJ");
+        assert_eq!(captures["2"], "123");
+        assert_eq!(captures.len(), 4);
+        assert_eq!(pattern.captures("proj-123"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn unmatched_optional_groups_are_omitted() -> Result<()> {
+        let pattern = from_str::<Pattern>(r"'a(b)?'")?;
+        let captures = pattern.captures("a").expect("pattern to match");
+        assert_eq!(captures.get("1"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_pattern_route() -> Result<()> {
+        let route = from_str::<PatternRoute>(
+            r#"
+            pattern: 'CVE-\d+-\d+'
+            path: "https://nvd.nist.gov/vuln/detail/{{query}}"
+            max_args: 1
+            "#,
+        )?;
+        assert!(route.pattern.is_match("CVE-2024-1234"));
+        assert_eq!(
+            route.route.path,
+            "https://nvd.nist.gov/vuln/detail/{{query}}"
         );
+        assert_eq!(route.route.max_args, Some(1));
         Ok(())
     }
+
+    #[test]
+    fn pattern_route_without_keywords() {
+        assert!(from_str::<PatternRoute>("pattern: a\npath: b\naliases: [c]").is_err());
+        assert!(from_str::<PatternRoute>("pattern: a\npath: b\nroutes: {c: d}").is_err());
+    }
+}
+
+#[cfg(test)]
+mod bind_params {
+    use super::*;
+    use anyhow::Result;
+    use serde_yaml::from_str;
+
+    fn jira_route() -> Result<Route> {
+        Ok(from_str(
+            r#"
+            path: "https://jira/browse/{{ticket}}"
+            params:
+              - name: ticket
+                pattern: "[A-Z]+-\\d+"
+              - name: n
+                type: int
+                default: 1
+            "#,
+        )?)
+    }
+
+    #[test]
+    fn no_params_binds_nothing() {
+        assert_eq!(Route::from("a").bind_params(&["a", "b"]), Some(Vec::new()));
+    }
+
+    #[test]
+    fn valid_args_are_bound() -> Result<()> {
+        assert_eq!(
+            jira_route()?.bind_params(&["PROJ-123", "5"]),
+            Some(vec![("ticket", "PROJ-123"), ("n", "5")])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_args_use_defaults() -> Result<()> {
+        assert_eq!(
+            jira_route()?.bind_params(&["PROJ-123"]),
+            Some(vec![("ticket", "PROJ-123"), ("n", "1")])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_required_args_are_rejected() -> Result<()> {
+        assert_eq!(jira_route()?.bind_params(&[]), None);
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_args_are_rejected() -> Result<()> {
+        let route = jira_route()?;
+        assert_eq!(route.bind_params(&["proj-123"]), None);
+        assert_eq!(route.bind_params(&["xPROJ-123"]), None);
+        assert_eq!(route.bind_params(&["PROJ-123", "five"]), None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod default_routes {
+    use super::*;
+    use anyhow::Result;
+
+    fn parse(default_route: &str) -> Result<Vec<String>> {
+        let config: Config = serde_yaml::from_str(&format!(
+            "bind_address: a\npublic_address: b\ngroups: []\n{default_route}"
+        ))?;
+        Ok(config.default_routes)
+    }
+
+    #[test]
+    fn missing_is_empty() -> Result<()> {
+        assert!(parse("")?.is_empty());
+        assert!(parse("default_route: ~")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn single_keyword() -> Result<()> {
+        assert_eq!(parse("default_route: g")?, vec!["g"]);
+        Ok(())
+    }
+
+    #[test]
+    fn ordered_keywords() -> Result<()> {
+        assert_eq!(parse("default_route: [a, g]")?, vec!["a", "g"]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod executable_settings {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn missing_fields_are_defaults() -> Result<()> {
+        let settings: ExecutableSettings = serde_yaml::from_str("max_queued: 4")?;
+        assert_eq!(settings.max_queued, 4);
+        assert_eq!(
+            settings.max_concurrent,
+            ExecutableSettings::default().max_concurrent
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn zero_max_concurrent_fails() {
+        assert!(serde_yaml::from_str::<ExecutableSettings>("max_concurrent: 0").is_err());
+        assert!(serde_yaml::from_str::<ExecutableSettings>("max_concurrent: 1").is_ok());
+    }
 }
 
 #[cfg(test)]
@@ -406,10 +1760,10 @@ mod read_config {
     #[test]
     fn config_too_large() -> Result<()> {
         let mut config_file = tempfile::tempfile()?;
-        let size_to_write = (LARGE_FILE_SIZE_THRESHOLD + 1) as usize;
-        config_file.write(&[0].repeat(size_to_write))?;
+        let size_to_write = usize::try_from(LARGE_FILE_SIZE_THRESHOLD + 1)?;
+        config_file.write_all(&[0].repeat(size_to_write))?;
         match load_file(config_file, false) {
-            Err(BunBunError::ConfigTooLarge(size)) if size as usize == size_to_write => {}
+            Err(BunBunError::ConfigTooLarge(size)) if usize::try_from(size)? == size_to_write => {}
             Err(BunBunError::ConfigTooLarge(size)) => {
                 panic!("Mismatched size: {size} != {size_to_write}")
             }
@@ -0,0 +1,139 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+// https://url.spec.whatwg.org/#fragment-percent-encode-set
+const FRAGMENT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'<')
+    .add(b'>')
+    .add(b'`')
+    .add(b'+')
+    .add(b'&') // Interpreted as a GET query
+    .add(b'#') // Interpreted as a hyperlink section target
+    .add(b'\'');
+
+// https://url.spec.whatwg.org/#query-percent-encode-set
+const QUERY_ENCODE_SET: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'#').add(b'<').add(b'>');
+
+// https://url.spec.whatwg.org/#path-percent-encode-set
+const PATH_ENCODE_SET: &AsciiSet = &QUERY_ENCODE_SET.add(b'?').add(b'`').add(b'{').add(b'}');
+
+/// The path percent-encode set, plus the characters that would otherwise end
+/// the segment or be interpreted as an escape.
+const PATH_SEGMENT_ENCODE_SET: &AsciiSet = &PATH_ENCODE_SET.add(b'/').add(b'\\').add(b'%');
+
+// https://url.spec.whatwg.org/#component-percent-encode-set
+const COMPONENT_ENCODE_SET: &AsciiSet = &PATH_ENCODE_SET
+    .add(b'/')
+    .add(b':')
+    .add(b';')
+    .add(b'=')
+    .add(b'@')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'|')
+    .add(b'$')
+    .add(b'%')
+    .add(b'&')
+    .add(b'+')
+    .add(b',');
+
+// https://url.spec.whatwg.org/#application-x-www-form-urlencoded-percent-encode-set
+const FORM_ENCODE_SET: &AsciiSet = &COMPONENT_ENCODE_SET
+    .add(b'!')
+    .add(b'\'')
+    .add(b'(')
+    .add(b')')
+    .add(b'~');
+
+/// How user input is escaped before being substituted into a route's path.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// Safe for use anywhere after a `#`, and for most query strings.
+    #[default]
+    Fragment,
+    /// Safe for use as a single path segment, so `/` is escaped.
+    PathSegment,
+    /// Safe for use as a single key or value in a query string.
+    QueryComponent,
+    /// Like `QueryComponent`, but spaces are encoded as `+`.
+    Form,
+    /// No escaping at all.
+    Raw,
+}
+
+impl Encoding {
+    /// Every encoding that can be used as a template helper, alongside the
+    /// helper's name.
+    pub const HELPERS: [(&'static str, Self); 4] = [
+        ("fragment", Self::Fragment),
+        ("path_segment", Self::PathSegment),
+        ("query_component", Self::QueryComponent),
+        ("form", Self::Form),
+    ];
+
+    pub fn encode(self, input: &str) -> Cow<'_, str> {
+        match self {
+            Self::Fragment => utf8_percent_encode(input, FRAGMENT_ENCODE_SET).into(),
+            Self::PathSegment => utf8_percent_encode(input, PATH_SEGMENT_ENCODE_SET).into(),
+            Self::QueryComponent => utf8_percent_encode(input, COMPONENT_ENCODE_SET).into(),
+            // Any literal `%` is escaped, so every remaining `%20` must have
+            // been a space.
+            Self::Form => utf8_percent_encode(input, FORM_ENCODE_SET)
+                .to_string()
+                .replace("%20", "+")
+                .into(),
+            Self::Raw => input.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod encode {
+    use super::Encoding;
+
+    const INPUT: &str = "a b/c?d=e&f+g%h#i'j";
+
+    #[test]
+    fn fragment() {
+        assert_eq!(
+            Encoding::Fragment.encode(INPUT),
+            "a%20b/c?d=e%26f%2Bg%h%23i%27j"
+        );
+    }
+
+    #[test]
+    fn path_segment() {
+        assert_eq!(
+            Encoding::PathSegment.encode(INPUT),
+            "a%20b%2Fc%3Fd=e&f+g%25h%23i'j"
+        );
+    }
+
+    #[test]
+    fn query_component() {
+        assert_eq!(
+            Encoding::QueryComponent.encode(INPUT),
+            "a%20b%2Fc%3Fd%3De%26f%2Bg%25h%23i'j"
+        );
+    }
+
+    #[test]
+    fn form() {
+        assert_eq!(
+            Encoding::Form.encode(INPUT),
+            "a+b%2Fc%3Fd%3De%26f%2Bg%25h%23i%27j"
+        );
+        assert_eq!(Encoding::Form.encode("%20 "), "%2520+");
+    }
+
+    #[test]
+    fn raw() {
+        assert_eq!(Encoding::Raw.encode(INPUT), INPUT);
+    }
+}
@@ -8,6 +8,17 @@ pub enum BunBunError {
     Parse(serde_yaml::Error),
     Watch(hotwatch::Error),
     CustomProgram(String),
+    ProgramTimedOut(std::time::Duration),
+    ProgramOutputTooLarge(usize),
+    InvalidProgramOutput(String),
+    ExecutorBusy,
+    WorkerExited,
+    Wasm(wasmi::Error),
+    Script(Box<rhai::EvalAltResult>),
+    Http(hyper::Error),
+    UpstreamStatus(u16),
+    HopLoop(Vec<String>),
+    HopTooDeep(usize),
     NoValidConfigPath,
     InvalidConfigPath(std::path::PathBuf, std::io::Error),
     ConfigTooLarge(u64),
@@ -24,9 +35,26 @@ impl fmt::Display for BunBunError {
             Self::Parse(e) => e.fmt(f),
             Self::Watch(e) => e.fmt(f),
             Self::CustomProgram(msg) => msg.fmt(f),
+            Self::ProgramTimedOut(timeout) => {
+                write!(f, "The program didn't finish within {}ms", timeout.as_millis())
+            }
+            Self::ProgramOutputTooLarge(limit) => {
+                write!(f, "The program wrote more than {limit} bytes of output")
+            }
+            Self::InvalidProgramOutput(reason) => {
+                write!(f, "The program gave an invalid response: {reason}")
+            }
+            Self::HopLoop(queries) => write!(f, "The hop looped: {}", queries.join(" -> ")),
+            Self::HopTooDeep(limit) => write!(f, "The hop was rewritten more than {limit} times"),
+            Self::ExecutorBusy => write!(f, "Too many programs are running or waiting to run"),
+            Self::WorkerExited => write!(f, "The worker exited without responding"),
+            Self::Wasm(e) => e.fmt(f),
+            Self::Script(e) => e.fmt(f),
+            Self::Http(e) => e.fmt(f),
+            Self::UpstreamStatus(status) => write!(f, "The endpoint responded with {status}"),
             Self::NoValidConfigPath => write!(f, "No valid config path was found!"),
             Self::InvalidConfigPath(path, reason) => {
-                write!(f, "Failed to access {path:?}: {reason}")
+                write!(f, "Failed to access {}: {reason}", path.display())
             }
             Self::ConfigTooLarge(size) => write!(f, "The config file was too large ({size} bytes)! Pass in --large-config to bypass this check."),
             Self::ZeroByteConfig => write!(f, "The config provided reported a size of 0 bytes. Please check your config path!"),
@@ -51,3 +79,6 @@ from_error!(std::io::Error, Io);
 from_error!(serde_yaml::Error, Parse);
 from_error!(hotwatch::Error, Watch);
 from_error!(serde_json::Error, JsonParse);
+from_error!(wasmi::Error, Wasm);
+from_error!(Box<rhai::EvalAltResult>, Script);
+from_error!(hyper::Error, Http);
@@ -0,0 +1,137 @@
+use crate::config::{ExecutableSettings, Route};
+use crate::BunBunError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits on a single execution of an executable route.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecLimits {
+    pub timeout: Duration,
+    pub max_output_bytes: usize,
+}
+
+impl ExecLimits {
+    /// Returns the limits of the route, using the global settings for any
+    /// limits that the route didn't override.
+    pub fn new(route: &Route, defaults: &ExecutableSettings) -> Self {
+        Self {
+            timeout: Duration::from_millis(route.timeout_ms.unwrap_or(defaults.timeout_ms)),
+            max_output_bytes: route.max_output_bytes.unwrap_or(defaults.max_output_bytes),
+        }
+    }
+}
+
+/// Limits how many executables may run at once. Hops past the limit wait in a
+/// queue of bounded length for their turn, so that a burst of hops to
+/// executable routes can't exhaust the server's resources.
+#[derive(Debug)]
+pub struct Executor {
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+    max_queued: usize,
+}
+
+impl Executor {
+    pub fn new(settings: &ExecutableSettings) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(settings.max_concurrent)),
+            queued: AtomicUsize::new(0),
+            max_queued: settings.max_queued,
+        }
+    }
+
+    /// Waits until an executable may be run, returning a permit that must be
+    /// held for as long as it runs. Returns an error without waiting if the
+    /// queue is already full.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, BunBunError> {
+        if let Ok(permit) = Arc::clone(&self.permits).try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        let _slot = QueueSlot::take(&self.queued, self.max_queued)?;
+        Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .map_err(|_| BunBunError::ExecutorBusy)
+    }
+}
+
+/// A place in the queue, which is given up when dropped. This way, the place is
+/// freed even if the hop waiting in it was cancelled.
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl<'a> QueueSlot<'a> {
+    fn take(queued: &'a AtomicUsize, max_queued: usize) -> Result<Self, BunBunError> {
+        queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| {
+                (queued < max_queued).then_some(queued + 1)
+            })
+            .map(|_| Self(queued))
+            .map_err(|_| BunBunError::ExecutorBusy)
+    }
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod acquire {
+    use super::*;
+    use tokio::time::timeout;
+
+    fn executor(max_concurrent: usize, max_queued: usize) -> Executor {
+        Executor::new(&ExecutableSettings {
+            max_concurrent,
+            max_queued,
+            ..ExecutableSettings::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn permits_are_released_on_drop() {
+        let executor = executor(1, 0);
+        let permit = executor.acquire().await;
+        assert!(permit.is_ok());
+        drop(permit);
+        assert!(executor.acquire().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn full_queue_is_rejected() {
+        let executor = executor(1, 0);
+        let _permit = executor.acquire().await;
+        assert!(matches!(
+            executor.acquire().await,
+            Err(BunBunError::ExecutorBusy)
+        ));
+    }
+
+    #[tokio::test]
+    async fn queued_hops_wait_for_permit() {
+        let executor = executor(1, 1);
+        let permit = executor.acquire().await;
+
+        // The queue has room, so this waits rather than failing
+        let waiting = timeout(Duration::from_millis(50), executor.acquire()).await;
+        assert!(waiting.is_err());
+
+        drop(permit);
+        assert!(executor.acquire().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn cancelled_hops_leave_queue() {
+        let executor = executor(1, 1);
+        let _permit = executor.acquire().await;
+        for _ in 0..3 {
+            let waiting = timeout(Duration::from_millis(10), executor.acquire()).await;
+            assert!(waiting.is_err());
+        }
+        assert_eq!(executor.queued.load(Ordering::Acquire), 0);
+    }
+}
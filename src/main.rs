@@ -1,4 +1,6 @@
-#![forbid(unsafe_code)]
+// Denied rather than forbidden so that `sandbox::limit_resources` alone can
+// opt out, as limiting a child process requires an unsafe pre-exec hook.
+#![deny(unsafe_code)]
 #![deny(missing_docs)]
 #![warn(clippy::nursery, clippy::pedantic)]
 
@@ -6,40 +8,91 @@
 //! search engine and quick-jump tool in one small binary. For information on
 //! usage, please take a look at the readme.
 
-use crate::config::{get_config_data, load_custom_file, load_file, FileData, Route, RouteGroup};
+use crate::config::{
+    get_config_data, load_custom_file, load_file, ExecutableSettings, FileData, PatternRoute,
+    Route, RouteGroup,
+};
 use anyhow::Result;
 use arc_swap::ArcSwap;
 use axum::routing::get;
 use axum::{Extension, Router};
+use cache::ResultCache;
+use callout::HttpClient;
 use clap::Parser;
+use encoding::Encoding;
 use error::BunBunError;
+use executor::Executor;
 use handlebars::Handlebars;
 use hotwatch::{Event, Hotwatch};
+use routes::HopResponse;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 use tracing::{debug, info, trace, warn};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-
+use trie::RouteTrie;
+use wasm::WasmModule;
+use worker::WorkerPool;
+
+mod argv;
+mod builtin;
+mod cache;
+mod callout;
 mod cli;
 mod config;
+mod encoding;
 #[cfg(not(tarpaulin_include))]
 mod error;
+mod executor;
 mod routes;
+mod sandbox;
+mod script;
 #[cfg(not(tarpaulin_include))]
 mod template_args;
+mod template_helpers;
+#[cfg(test)]
+mod test_utils;
+mod trie;
+mod wasm;
+mod worker;
 
 /// Dynamic variables that either need to be present at runtime, or can be
 /// changed during runtime.
 pub struct State {
     public_address: String,
-    default_route: Option<String>,
+    /// Keywords of the routes to fall back to, in order of priority.
+    default_routes: Vec<String>,
+    /// Routes for queries matching a pattern, in order of priority.
+    patterns: Vec<PatternRoute>,
+    bang_sigil: Option<String>,
+    executables: ExecutableSettings,
+    /// Shared by every executable route. As this is recreated when the config
+    /// is reloaded, executables that were started beforehand aren't counted
+    /// towards the new limits.
+    executor: Executor,
+    /// Processes of worker routes, keyed by path. Like the executor, these are
+    /// recreated when the config is reloaded, which stops the old processes.
+    workers: HashMap<String, WorkerPool>,
+    /// Modules of wasm routes, keyed by path. These are compiled whenever the
+    /// config is loaded, so changes to a module apply once the config changes.
+    wasm_modules: HashMap<String, WasmModule>,
+    /// Results of routes that cache them. This is emptied when the config is
+    /// reloaded, as the routes may have changed.
+    cache: ResultCache<HopResponse<'static>>,
     groups: Vec<RouteGroup>,
-    /// Cached, flattened mapping of all routes and their destinations.
-    routes: HashMap<String, Route>,
+    /// Cached, flattened mapping of all routes and their destinations, keyed
+    /// by their (potentially multi-word) keywords.
+    routes: RouteTrie,
 }
 
+/// Renders route paths. Unlike the page templates, route paths are URLs rather
+/// than HTML, so they aren't HTML-escaped.
+#[derive(Clone)]
+pub struct PathRenderer(Handlebars<'static>);
+
 #[tokio::main]
 #[cfg(not(tarpaulin_include))]
 async fn main() -> Result<()> {
@@ -59,11 +112,22 @@ async fn main() -> Result<()> {
 
     let conf_data = opts.config.map_or_else(get_config_data, load_custom_file)?;
 
-    let conf = load_file(conf_data.file.try_clone()?, opts.large_config)?;
+    let mut conf = load_file(conf_data.file.try_clone()?, opts.large_config)?;
+    conf.resolve_relative_paths(conf_data.path.parent().unwrap_or_else(|| Path::new("")));
+    let routes = cache_routes(conf.groups.clone());
+    let workers = worker::pools(all_routes(&routes, &conf.patterns))?;
+    let wasm_modules = wasm::modules(all_routes(&routes, &conf.patterns))?;
     let state = Arc::from(ArcSwap::from_pointee(State {
         public_address: conf.public_address,
-        default_route: conf.default_route,
-        routes: cache_routes(conf.groups.clone()),
+        default_routes: conf.default_routes,
+        patterns: conf.patterns,
+        bang_sigil: conf.bang_sigil,
+        executor: Executor::new(&conf.executables),
+        cache: ResultCache::new(conf.executables.cache_capacity),
+        executables: conf.executables,
+        workers,
+        wasm_modules,
+        routes,
         groups: conf.groups,
     }));
 
@@ -76,6 +140,8 @@ async fn main() -> Result<()> {
         .route("/ls", get(routes::list))
         .route("/hop", get(routes::hop))
         .layer(Extension(compile_templates()?))
+        .layer(Extension(compile_path_renderer()))
+        .layer(Extension(HttpClient::default()))
         .layer(Extension(state));
 
     let bind_addr = conf.bind_address.parse()?;
@@ -83,28 +149,77 @@ async fn main() -> Result<()> {
     info!("Starting server at {bind_addr}");
 
     axum::Server::bind(&bind_addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
 }
 
-/// Generates a hashmap of routes from the data structure created by the config
+/// Returns every route, whether it's reached by keyword or by pattern.
+fn all_routes<'a>(
+    routes: &'a RouteTrie,
+    patterns: &'a [PatternRoute],
+) -> impl Iterator<Item = &'a Route> {
+    routes
+        .routes()
+        .into_iter()
+        .map(|(_, route)| route)
+        .chain(patterns.iter().map(|pattern| &pattern.route))
+}
+
+/// Generates a trie of routes from the data structure created by the config
 /// file. This should improve runtime performance and is a better solution than
 /// just iterating over the config object for every hop resolution.
-fn cache_routes(groups: Vec<RouteGroup>) -> HashMap<String, Route> {
-    let mut mapping = HashMap::new();
+fn cache_routes(groups: Vec<RouteGroup>) -> RouteTrie {
+    let mut mapping = RouteTrie::default();
     for group in groups {
         for (kw, dest) in group.routes {
-            // This function isn't called often enough to not be a performance issue.
-            if let Some(old_value) = mapping.insert(kw.clone(), dest.clone()) {
-                trace!("Overriding {kw} route from {old_value} to {dest}.");
+            // Routes in hidden groups shouldn't leak out through other pages,
+            // such as the suggestions on the not found page.
+            cache_route(&mut mapping, &[], &kw, dest, group.hidden);
+        }
+    }
+    mapping
+}
+
+/// Inserts a route under its keyword and aliases, each prefixed by every
+/// keyword its parent route can be reached by. Its sub-routes are then inserted
+/// in the same manner.
+fn cache_route(
+    mapping: &mut RouteTrie,
+    parent_keywords: &[String],
+    kw: &str,
+    mut dest: Route,
+    hidden: bool,
+) {
+    dest.hidden |= hidden;
+    let sub_routes = std::mem::take(&mut dest.routes);
+    let keywords = std::iter::once(kw)
+        .chain(dest.aliases.iter().map(String::as_str))
+        .flat_map(|kw| {
+            if parent_keywords.is_empty() {
+                vec![kw.to_owned()]
             } else {
-                trace!("Inserting {kw} into mapping.");
+                parent_keywords
+                    .iter()
+                    .map(|parent| format!("{parent} {kw}"))
+                    .collect()
             }
+        })
+        .collect::<Vec<_>>();
+
+    for kw in &keywords {
+        // This function isn't called often enough to not be a performance issue.
+        if let Some(old_value) = mapping.insert(kw, dest.clone()) {
+            trace!("Overriding {kw} route from {old_value} to {dest}.");
+        } else {
+            trace!("Inserting {kw} into mapping.");
         }
     }
-    mapping
+
+    for (sub_kw, sub_dest) in sub_routes {
+        cache_route(mapping, &keywords, &sub_kw, sub_dest, dest.hidden);
+    }
 }
 
 /// Returns an instance with all pre-generated templates included into the
@@ -128,10 +243,23 @@ fn compile_templates() -> Result<Handlebars<'static>> {
       )*
     };
   }
-    register_template!["index", "list", "opensearch"];
+    register_template!["index", "list", "not_found", "open_all", "opensearch"];
     Ok(handlebars)
 }
 
+/// Returns an instance for rendering route paths, with all helpers available to
+/// route paths registered.
+fn compile_path_renderer() -> PathRenderer {
+    let mut handlebars = Handlebars::new();
+    handlebars.set_strict_mode(true);
+    handlebars.register_escape_fn(handlebars::no_escape);
+    handlebars.register_helper("rest", Box::new(template_helpers::rest));
+    for (name, encoding) in Encoding::HELPERS {
+        handlebars.register_helper(name, Box::new(template_helpers::Encode(encoding)));
+    }
+    PathRenderer(handlebars)
+}
+
 /// Starts the watch on a file, if possible. This will only return an Error if
 /// the notify library (used by Hotwatch) fails to initialize, which is
 /// considered to be a more serve error as it may be indicative of a low-level
@@ -149,6 +277,7 @@ fn start_watch(
 ) -> Result<Hotwatch> {
     let mut watch = Hotwatch::new_with_custom_delay(Duration::from_millis(500))?;
     let FileData { path, mut file } = config_data;
+    let config_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
     let watch_result = watch.watch(&path, move |e: Event| {
         if let Event::Create(ref path) = e {
             file = load_custom_file(path).expect("file to exist at path").file;
@@ -163,11 +292,29 @@ fn start_watch(
                     file.try_clone().expect("Failed to clone file handle"),
                     large_config,
                 ) {
-                    Ok(conf) => {
+                    Ok(mut conf) => {
+                        conf.resolve_relative_paths(&config_dir);
+                        let routes = cache_routes(conf.groups.clone());
+                        let workers = match worker::pools(all_routes(&routes, &conf.patterns)) {
+                            Ok(workers) => workers,
+                            Err(e) => return warn!("Failed to update config file: {e}"),
+                        };
+                        let wasm_modules = match wasm::modules(all_routes(&routes, &conf.patterns))
+                        {
+                            Ok(modules) => modules,
+                            Err(e) => return warn!("Failed to update config file: {e}"),
+                        };
                         state.store(Arc::new(State {
                             public_address: conf.public_address,
-                            default_route: conf.default_route,
-                            routes: cache_routes(conf.groups.clone()),
+                            default_routes: conf.default_routes,
+                            patterns: conf.patterns,
+                            bang_sigil: conf.bang_sigil,
+                            executor: Executor::new(&conf.executables),
+                            cache: ResultCache::new(conf.executables.cache_capacity),
+                            executables: conf.executables,
+                            workers,
+                            wasm_modules,
+                            routes,
                             groups: conf.groups,
                         }));
                         info!("Successfully updated active state");
@@ -180,7 +327,7 @@ fn start_watch(
     });
 
     match watch_result {
-        Ok(_) => info!("Watcher is now watching {path:?}"),
+        Ok(()) => info!("Watcher is now watching {path:?}"),
         Err(e) => {
             warn!("Couldn't watch {path:?}: {e}. Changes to this file won't be seen!");
         }
@@ -192,19 +339,26 @@ fn start_watch(
 #[cfg(test)]
 mod cache_routes {
     use super::*;
-    use std::iter::FromIterator;
+    use std::collections::HashMap;
 
     fn generate_external_routes(routes: &[(&'static str, &'static str)]) -> HashMap<String, Route> {
-        HashMap::from_iter(
-            routes
-                .into_iter()
-                .map(|(key, value)| ((*key).to_owned(), Route::from(*value))),
-        )
+        routes
+            .iter()
+            .map(|(key, value)| ((*key).to_owned(), Route::from(*value)))
+            .collect()
+    }
+
+    fn generate_trie(routes: &[(&'static str, &'static str)]) -> RouteTrie {
+        let mut trie = RouteTrie::default();
+        for (key, value) in routes {
+            trie.insert(key, Route::from(*value));
+        }
+        trie
     }
 
     #[test]
     fn empty_groups_yield_empty_routes() {
-        assert_eq!(cache_routes(Vec::new()), HashMap::new());
+        assert_eq!(cache_routes(Vec::new()), RouteTrie::default());
     }
 
     #[test]
@@ -225,7 +379,7 @@ mod cache_routes {
 
         assert_eq!(
             cache_routes(vec![group1, group2]),
-            generate_external_routes(&[("a", "b"), ("c", "d"), ("1", "2"), ("3", "4")])
+            generate_trie(&[("a", "b"), ("c", "d"), ("1", "2"), ("3", "4")])
         );
     }
 
@@ -247,7 +401,7 @@ mod cache_routes {
 
         assert_eq!(
             cache_routes(vec![group1.clone(), group2]),
-            generate_external_routes(&[("a", "1"), ("c", "2")])
+            generate_trie(&[("a", "1"), ("c", "2")])
         );
 
         let group3 = RouteGroup {
@@ -259,7 +413,94 @@ mod cache_routes {
 
         assert_eq!(
             cache_routes(vec![group1, group3]),
-            generate_external_routes(&[("a", "1"), ("b", "2"), ("c", "d")])
+            generate_trie(&[("a", "1"), ("b", "2"), ("c", "d")])
+        );
+    }
+
+    #[test]
+    fn hidden_groups_hide_their_routes() {
+        let group = RouteGroup {
+            name: String::from("x"),
+            description: None,
+            routes: generate_external_routes(&[("a", "1")]),
+            hidden: true,
+        };
+
+        let mut expected = Route::from("1");
+        expected.hidden = true;
+        assert_eq!(cache_routes(vec![group]).get("a"), Some(&expected));
+    }
+
+    #[test]
+    fn aliases_are_registered() {
+        let mut route = Route::from("1");
+        route.aliases = vec![String::from("b"), String::from("c d")];
+        let group = RouteGroup {
+            name: String::from("x"),
+            description: None,
+            routes: HashMap::from([(String::from("a"), route.clone())]),
+            hidden: false,
+        };
+
+        let routes = cache_routes(vec![group]);
+        assert_eq!(routes.get("a"), Some(&route));
+        assert_eq!(routes.get("b"), Some(&route));
+        assert_eq!(routes.get("c d"), Some(&route));
+    }
+
+    #[test]
+    fn sub_routes_are_registered_under_parent_keywords() {
+        let mut pulls = Route::from("2");
+        pulls.aliases = vec![String::from("prs")];
+        let mut parent = Route::from("1");
+        parent.aliases = vec![String::from("github")];
+        let expected_parent = parent.clone();
+        parent.routes = HashMap::from([(String::from("pr"), pulls.clone())]);
+        let group = RouteGroup {
+            name: String::from("x"),
+            description: None,
+            routes: HashMap::from([(String::from("gh"), parent)]),
+            hidden: false,
+        };
+
+        let routes = cache_routes(vec![group]);
+        assert_eq!(routes.get("gh"), Some(&expected_parent));
+        assert_eq!(routes.get("github"), Some(&expected_parent));
+        for keyword in ["gh pr", "gh prs", "github pr", "github prs"] {
+            assert_eq!(routes.get(keyword), Some(&pulls));
+        }
+        assert_eq!(routes.get("pr"), None);
+    }
+
+    #[test]
+    fn hidden_routes_hide_their_sub_routes() {
+        let mut parent = Route::from("1");
+        parent.hidden = true;
+        parent.routes = HashMap::from([(String::from("b"), Route::from("2"))]);
+        let group = RouteGroup {
+            name: String::from("x"),
+            description: None,
+            routes: HashMap::from([(String::from("a"), parent)]),
+            hidden: false,
+        };
+
+        let mut expected = Route::from("2");
+        expected.hidden = true;
+        assert_eq!(cache_routes(vec![group]).get("a b"), Some(&expected));
+    }
+
+    #[test]
+    fn multi_word_keywords_are_normalized() {
+        let group = RouteGroup {
+            name: String::from("x"),
+            description: None,
+            routes: generate_external_routes(&[("a  b", "1"), ("a", "2")]),
+            hidden: false,
+        };
+
+        assert_eq!(
+            cache_routes(vec![group]),
+            generate_trie(&[("a b", "1"), ("a", "2")])
         );
     }
 }
@@ -276,4 +517,82 @@ mod compile_templates {
     fn templates_compile() {
         let _ = compile_templates();
     }
+
+    #[test]
+    fn open_all_escapes_urls() -> anyhow::Result<()> {
+        let urls = [String::from("https://a.com/?q=<b>")];
+        let page =
+            compile_templates()?.render("open_all", &crate::template_args::open_all("x", &urls))?;
+        assert!(page.contains(r#"href="https://a.com/?q&#x3D;&lt;b&gt;""#));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod compile_path_renderer {
+    use super::compile_path_renderer;
+    use crate::encoding::Encoding;
+    use crate::template_args;
+    use anyhow::Result;
+    use std::collections::HashMap;
+
+    fn render(path: &str, query: &str, encoding: Encoding) -> Result<String> {
+        let args = query.split_ascii_whitespace().collect::<Vec<_>>();
+        let params = [("name", "x&y")];
+        let captures = HashMap::from([(String::from("1"), String::from("c?d"))]);
+        let rendered = compile_path_renderer().0.render_template(
+            path,
+            &template_args::query(query, &args, &params, &captures, encoding),
+        )?;
+        Ok(rendered)
+    }
+
+    #[test]
+    fn output_is_not_html_escaped() -> Result<()> {
+        assert_eq!(render("?q={{query}}", "a=b", Encoding::Fragment)?, "?q=a=b");
+        assert_eq!(
+            render("{{raw.query}}", "<a&b>", Encoding::Fragment)?,
+            "<a&b>"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn values_use_route_encoding() -> Result<()> {
+        assert_eq!(
+            render(
+                "{{query}}|{{args.[1]}}|{{rest 1}}|{{name}}",
+                "a/b c d",
+                Encoding::Form
+            )?,
+            "a%2Fb+c+d|c|c+d|x%26y"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn captures_use_route_encoding() -> Result<()> {
+        assert_eq!(
+            render(
+                "{{captures.[1]}}|{{raw.captures.[1]}}",
+                "a",
+                Encoding::QueryComponent
+            )?,
+            "c%3Fd|c?d"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn helpers_mix_encodings() -> Result<()> {
+        assert_eq!(
+            render(
+                "/{{path_segment raw.args.[0]}}?q={{form raw.query}}&n={{raw.name}}",
+                "a/b c",
+                Encoding::Fragment
+            )?,
+            "/a%2Fb?q=a%2Fb+c&n=x&y"
+        );
+        Ok(())
+    }
 }
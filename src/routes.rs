@@ -1,32 +1,64 @@
-use crate::config::{Route as ConfigRoute, RouteType};
-use crate::{template_args, BunBunError, Route, State};
+use crate::argv::ArgsMode;
+use crate::builtin::Builtin;
+use crate::callout::HttpClient;
+use crate::config::{Pattern, PatternRoute, Route as ConfigRoute, RouteType, Sandbox};
+use crate::encoding::Encoding;
+use crate::executor::{ExecLimits, Executor};
+use crate::sandbox;
+use crate::script::ScriptLimits;
+use crate::trie::RouteTrie;
+use crate::wasm::WasmLimits;
+use crate::{template_args, BunBunError, PathRenderer, Route, State};
 use arc_swap::ArcSwap;
 use axum::body::{boxed, Bytes, Empty, Full};
-use axum::extract::Query;
-use axum::http::{header, StatusCode};
+use axum::extract::{ConnectInfo, Query};
+use axum::http::header::{HeaderName, HeaderValue};
+use axum::http::{header, response, HeaderMap, StatusCode};
 use axum::response::{Html, IntoResponse, Response};
-use axum::Extension;
+use axum::{Extension, Json};
 use handlebars::Handlebars;
-use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
-use serde::Deserialize;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
-use std::process::Command;
+use std::process::Stdio;
 use std::sync::Arc;
-use tracing::{debug, error};
-
-// https://url.spec.whatwg.org/#fragment-percent-encode-set
-const FRAGMENT_ENCODE_SET: &AsciiSet = &CONTROLS
-    .add(b' ')
-    .add(b'"')
-    .add(b'<')
-    .add(b'>')
-    .add(b'`')
-    .add(b'+')
-    .add(b'&') // Interpreted as a GET query
-    .add(b'#') // Interpreted as a hyperlink section target
-    .add(b'\'');
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::Command;
+use tokio::task::spawn_blocking;
+use tokio::time::timeout;
+use tracing::{debug, error, trace, warn};
+
+/// The maximum number of routes suggested when a hop couldn't be resolved.
+const MAX_SUGGESTIONS: usize = 5;
+/// The request headers that are relayed to executables that want context.
+const RELAYED_HEADERS: &[&str] = &[
+    "accept-language",
+    "referer",
+    "user-agent",
+    "x-forwarded-for",
+];
+/// The response headers programs can't set, as they're managed by bunbun and
+/// the connection to the client.
+const RESERVED_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "keep-alive",
+    "location",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+/// The maximum number of aliases followed when resolving a single hop.
+const MAX_ALIAS_DEPTH: usize = 8;
+/// The maximum number of times a single hop may be rewritten by its routes.
+const MAX_REHOP_DEPTH: usize = 8;
 
 #[allow(clippy::unused_async)]
 pub async fn index(
@@ -81,113 +113,757 @@ pub struct SearchQuery {
     to: String,
 }
 
-#[allow(clippy::unused_async)]
 pub async fn hop(
     Extension(data): Extension<Arc<ArcSwap<State>>>,
     Extension(handlebars): Extension<Handlebars<'static>>,
+    Extension(path_renderer): Extension<PathRenderer>,
+    Extension(http_client): Extension<HttpClient>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Query(query): Query<SearchQuery>,
 ) -> impl IntoResponse {
-    let data = data.load();
-
-    match resolve_hop(&query.to, &data.routes, data.default_route.as_deref()) {
-        RouteResolution::Resolved { route: path, args } => {
-            let resolved_template = match path {
-                ConfigRoute {
-                    route_type: RouteType::Internal,
-                    path,
-                    ..
-                } => resolve_path(Path::new(path), &args),
-                ConfigRoute {
-                    route_type: RouteType::External,
-                    path,
-                    ..
-                } => Ok(HopAction::Redirect(Cow::Borrowed(path))),
-            };
+    let data = data.load_full();
+    let mut query = query.to;
+    // Queries that were rewritten by a route into another query, in order.
+    let mut previous_queries: Vec<String> = Vec::new();
 
-            match resolved_template {
-                Ok(HopAction::Redirect(path)) => {
-                    let rendered = handlebars
-                        .render_template(
-                            &path,
-                            &template_args::query(utf8_percent_encode(&args, FRAGMENT_ENCODE_SET)),
-                        )
-                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-                    Response::builder()
-                        .status(StatusCode::FOUND)
-                        .header(header::LOCATION, rendered)
-                        .body(boxed(Empty::new()))
-                }
-                Ok(HopAction::Body(body)) => Response::builder()
-                    .status(StatusCode::OK)
-                    .body(boxed(Full::new(Bytes::from(body)))),
-                Err(e) => {
-                    error!("Failed to redirect user for {path}: {e}");
-                    Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(boxed(Full::from("Something went wrong :(\n")))
+    loop {
+        let RouteResolution::Resolved {
+            route,
+            keyword,
+            pattern,
+            args,
+            raw_args,
+            captures,
+        } = follow_aliases(
+            &query,
+            &data.routes,
+            &data.patterns,
+            data.bang_sigil.as_deref(),
+            &data.default_routes,
+        )
+        else {
+            return not_found(&query, &data.routes, &handlebars, &headers);
+        };
+
+        let split_args = args.split_ascii_whitespace().collect::<Vec<_>>();
+        let hop = Hop {
+            query: &query,
+            keyword: keyword.as_deref(),
+            pattern,
+            args: &args,
+            raw_args: &raw_args,
+            split_args: &split_args,
+            captures: &captures,
+            client,
+            headers: &headers,
+            state: &data,
+            path_renderer: &path_renderer,
+            http_client: &http_client,
+            handlebars: &handlebars,
+        };
+
+        let response = match run_route(route, &hop).await {
+            Ok(HopResponse {
+                action: HopAction::Hop(next),
+                ..
+            }) => {
+                debug!("{route} rewrote {query} to {next}");
+                match rehop(&mut query, &mut previous_queries, &next) {
+                    Ok(()) => continue,
+                    Err(e) => Err(e),
                 }
             }
+            response => response,
+        };
+
+        if !previous_queries.is_empty() {
+            debug!(
+                "Followed hop chain {} -> {query}",
+                previous_queries.join(" -> ")
+            );
+        }
+
+        return match response {
+            Ok(HopResponse {
+                action: HopAction::Redirect(path),
+                status,
+                headers,
+            }) => {
+                let params = route.bind_params(&split_args).unwrap_or_default();
+                let rendered = path_renderer
+                    .0
+                    .render_template(
+                        &path,
+                        &template_args::query(
+                            &args,
+                            &split_args,
+                            &params,
+                            &captures,
+                            route.encoding,
+                        ),
+                    )
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                response_builder(status, headers)
+                    .header(header::LOCATION, rendered)
+                    .body(boxed(Empty::new()))
+            }
+            Ok(HopResponse {
+                action: HopAction::Body(body) | HopAction::Html(body),
+                status,
+                headers,
+            }) => response_builder(status, headers).body(boxed(Full::new(Bytes::from(body)))),
+            Ok(HopResponse {
+                action: HopAction::Hop(_),
+                ..
+            }) => unreachable!("hops are followed above"),
+            Err(e) => {
+                error!("Failed to redirect user for {route}: {e}");
+                error_response(&e)
+            }
+        }
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
+
+/// Replaces the query with the next query it was rewritten to, remembering the
+/// query that was replaced. Fails if the next query was already hopped to, or
+/// if the query was rewritten too many times.
+fn rehop(
+    query: &mut String,
+    previous_queries: &mut Vec<String>,
+    next: &str,
+) -> Result<(), BunBunError> {
+    let next = next.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+    if next == *query || previous_queries.contains(&next) {
+        let chain = [previous_queries.as_slice(), &[query.clone(), next]].concat();
+        return Err(BunBunError::HopLoop(chain));
+    }
+
+    if previous_queries.len() >= MAX_REHOP_DEPTH {
+        return Err(BunBunError::HopTooDeep(MAX_REHOP_DEPTH));
+    }
+
+    previous_queries.push(std::mem::replace(query, next));
+    Ok(())
+}
+
+/// A resolved hop, with everything its route may need to answer it.
+struct Hop<'a> {
+    query: &'a str,
+    keyword: Option<&'a str>,
+    pattern: Option<&'a Pattern>,
+    args: &'a str,
+    /// The arguments as the user spaced and quoted them.
+    raw_args: &'a str,
+    split_args: &'a [&'a str],
+    captures: &'a HashMap<String, String>,
+    client: SocketAddr,
+    headers: &'a HeaderMap,
+    state: &'a State,
+    path_renderer: &'a PathRenderer,
+    http_client: &'a HttpClient,
+    handlebars: &'a Handlebars<'static>,
+}
+
+/// Answers the hop with the provided route.
+async fn run_route<'a>(route: &'a Route, hop: &Hop<'_>) -> Result<HopResponse<'a>, BunBunError> {
+    match route.route_type {
+        RouteType::Internal | RouteType::Worker | RouteType::Wasm | RouteType::Script => {
+            run_cached(route, hop).await
+        }
+        RouteType::Http => match (run_cached(route, hop).await, &route.fallback) {
+            (Err(e), Some(fallback)) => {
+                warn!("{route} failed, falling back to {fallback}: {e}");
+                Ok(HopResponse::from(HopAction::Hop(format!(
+                    "{fallback} {}",
+                    hop.args
+                ))))
+            }
+            (response, _) => response,
+        },
+        RouteType::External => Ok(HopResponse::from(HopAction::Redirect(Cow::Borrowed(
+            &route.path,
+        )))),
+        RouteType::StaticFile => {
+            let body = tokio::fs::read_to_string(&route.path).await?;
+            let mut response = HopResponse::from(HopAction::Body(body));
+            response.headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(static_file_type(Path::new(&route.path))),
+            );
+            Ok(response)
+        }
+        RouteType::Builtin => {
+            let builtin = Builtin::from_name(&route.path).ok_or_else(|| {
+                BunBunError::CustomProgram(format!("Unknown builtin {}", route.path))
+            })?;
+            Ok(builtin_response(builtin.run(hop.args)))
+        }
+        RouteType::OpenAll => {
+            let urls = render_templates(
+                route,
+                hop,
+                route.urls.iter().map(String::as_str),
+                route.encoding,
+            )?;
+            let page = hop
+                .handlebars
+                .render("open_all", &template_args::open_all(hop.query, &urls))
+                .map_err(|e| BunBunError::CustomProgram(e.to_string()))?;
+            Ok(HopResponse::from(HopAction::Html(page)))
+        }
+        RouteType::Alias => unreachable!("aliases are followed while resolving"),
+    }
+}
+
+/// Runs the program of the route, reusing an earlier result for the same
+/// arguments if the route caches its results. Only successful results are
+/// cached.
+async fn run_cached(route: &Route, hop: &Hop<'_>) -> Result<HopResponse<'static>, BunBunError> {
+    let Some(ttl) = route.cache_ttl.map(Duration::from_secs) else {
+        return run_program(route, hop).await;
+    };
+
+    let (identity, input) = cache_key(route, hop.pattern, &ExecContext::new(hop))?;
+    if let Some(response) = hop.state.cache.get(&identity, &input) {
+        return Ok(response);
+    }
+
+    let response = run_program(route, hop).await?;
+    hop.state
+        .cache
+        .insert(&identity, &input, response.clone(), ttl);
+    Ok(response)
+}
+
+/// Returns the key a run of the route is cached under. Routes are identified
+/// by the keyword or pattern they were reached by, as routes sharing a program
+/// may still run it differently. Alongside that is the query as the user
+/// spaced and quoted it, as programs may not split it on whitespace, or the
+/// entire context for routes that opted into it, so that their results aren't
+/// reused for hops from other clients.
+fn cache_key(
+    route: &Route,
+    pattern: Option<&Pattern>,
+    context: &ExecContext<'_>,
+) -> Result<(String, String), BunBunError> {
+    let identity = match (context.keyword, pattern) {
+        (Some(keyword), _) => format!("keyword {keyword}"),
+        (None, Some(pattern)) => format!("pattern {pattern}"),
+        (None, None) => String::new(),
+    };
+    let input = if route.context {
+        serde_json::to_string(context)?
+    } else {
+        context.query.to_owned()
+    };
+    Ok((identity, input))
+}
+
+/// Guesses the content type of a static file from its extension. Static files
+/// are always text, so anything unrecognized is plain text.
+fn static_file_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("svg") => "image/svg+xml",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+/// Responds with the text a builtin returned, or with the reason its arguments
+/// were rejected.
+fn builtin_response(result: Result<String, String>) -> HopResponse<'static> {
+    let (body, status) = match result {
+        Ok(text) => (text, StatusCode::OK),
+        Err(reason) => (reason, StatusCode::BAD_REQUEST),
+    };
+    let mut response = HopResponse::from(HopAction::Body(body));
+    response.status = status;
+    response.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    response
+}
+
+/// Runs the program of an exec, worker, wasm or script route, or calls the
+/// endpoint of an http route.
+async fn run_program(route: &Route, hop: &Hop<'_>) -> Result<HopResponse<'static>, BunBunError> {
+    match route.route_type {
+        RouteType::Internal => {
+            let context = route.context.then(|| ExecContext::new(hop));
+            let stdin = (route.args_mode == ArgsMode::Stdin).then_some(hop.raw_args);
+            resolve_path(
+                Path::new(&route.path),
+                &program_args(route, hop)?,
+                stdin,
+                ExecLimits::new(route, &hop.state.executables),
+                &hop.state.executor,
+                &route.sandbox,
+                context.as_ref(),
+            )
+            .await
+        }
+        RouteType::Worker => {
+            let pool = hop.state.workers.get(&route.path).ok_or_else(|| {
+                BunBunError::CustomProgram(format!("No worker pool for {}", route.path))
+            })?;
+            let request = serde_json::to_vec(&ExecContext::new(hop))?;
+            let response = pool
+                .request(
+                    &request,
+                    ExecLimits::new(route, &hop.state.executables),
+                    &hop.state.executor,
+                )
+                .await?;
+            parse_program_response(&response)
+        }
+        RouteType::Wasm => {
+            let module = hop
+                .state
+                .wasm_modules
+                .get(&route.path)
+                .cloned()
+                .ok_or_else(|| {
+                    BunBunError::CustomProgram(format!("No module for {}", route.path))
+                })?;
+            let input = serde_json::to_vec(&ExecContext::new(hop))?;
+            let limits = WasmLimits::new(route, &hop.state.executables);
+            let _permit = hop.state.executor.acquire().await?;
+            let output = spawn_blocking(move || module.call(&input, limits))
+                .await
+                .map_err(|e| BunBunError::CustomProgram(e.to_string()))??;
+            parse_program_response(&output)
+        }
+        RouteType::Script => {
+            let script = route.script.clone().ok_or_else(|| {
+                BunBunError::CustomProgram(String::from("Script route without a script"))
+            })?;
+            let query = hop.query.to_owned();
+            let args = hop.split_args.iter().map(|&arg| arg.to_owned()).collect();
+            let context = rhai::serde::to_dynamic(ExecContext::new(hop))?;
+            let limits = ScriptLimits::new(route, &hop.state.executables);
+            let _permit = hop.state.executor.acquire().await?;
+            let output = spawn_blocking(move || script.run(query, args, context, limits))
+                .await
+                .map_err(|e| BunBunError::CustomProgram(e.to_string()))??;
+            parse_program_response(&output)
+        }
+        RouteType::Http => {
+            let url = render_templates(route, hop, [route.path.as_str()], route.encoding)?
+                .pop()
+                .unwrap_or_default();
+            let values = render_templates(
+                route,
+                hop,
+                route.headers.values().map(String::as_str),
+                Encoding::Raw,
+            )?;
+            let headers = route.headers.keys().cloned().zip(values).collect();
+            let body = serde_json::to_vec(&ExecContext::new(hop))?;
+            let output = hop
+                .http_client
+                .post(
+                    &url,
+                    headers,
+                    body,
+                    ExecLimits::new(route, &hop.state.executables),
+                )
+                .await?;
+            parse_program_response(&output)
+        }
+        _ => unreachable!("only exec, worker, wasm, script and http routes run programs"),
+    }
+}
+
+/// Returns the arguments of an exec route's program, which are the rendered
+/// leading arguments of its command followed by the user's arguments. As they
+/// aren't part of a URL, templates are rendered without any encoding.
+fn program_args(route: &Route, hop: &Hop<'_>) -> Result<Vec<String>, BunBunError> {
+    let mut args = match &route.command {
+        Some(command) => render_templates(
+            route,
+            hop,
+            command.iter().skip(1).map(String::as_str),
+            Encoding::Raw,
+        )?,
+        None => Vec::new(),
+    };
+    args.extend(route.args_mode.args(hop.raw_args));
+    Ok(args)
+}
+
+/// Renders the templates with the hop's arguments, encoded as provided.
+fn render_templates<'t>(
+    route: &Route,
+    hop: &Hop<'_>,
+    templates: impl IntoIterator<Item = &'t str>,
+    encoding: Encoding,
+) -> Result<Vec<String>, BunBunError> {
+    let params = route.bind_params(hop.split_args).unwrap_or_default();
+    let template_args =
+        template_args::query(hop.args, hop.split_args, &params, hop.captures, encoding);
+    templates
+        .into_iter()
+        .map(|template| {
+            hop.path_renderer
+                .0
+                .render_template(template, &template_args)
+                .map_err(|e| {
+                    BunBunError::CustomProgram(format!("Failed to render {template}: {e}"))
+                })
+        })
+        .collect()
+}
+
+/// Returns a builder for a response with the provided status and headers.
+fn response_builder(status: StatusCode, headers: HeaderMap) -> response::Builder {
+    let mut builder = Response::builder().status(status);
+    if let Some(builder_headers) = builder.headers_mut() {
+        builder_headers.extend(headers);
+    }
+    builder
+}
+
+/// Responds to a hop that failed with the provided error. Only invalid output
+/// is explained to the user, as other errors may leak details of the server.
+fn error_response(e: &BunBunError) -> Result<Response, axum::http::Error> {
+    let (status, body) = match e {
+        BunBunError::ProgramTimedOut(_) => (StatusCode::GATEWAY_TIMEOUT, None),
+        BunBunError::ProgramOutputTooLarge(_)
+        | BunBunError::WorkerExited
+        | BunBunError::Http(_)
+        | BunBunError::UpstreamStatus(_) => (StatusCode::BAD_GATEWAY, None),
+        BunBunError::InvalidProgramOutput(_) => (StatusCode::BAD_GATEWAY, Some(format!("{e}\n"))),
+        BunBunError::ExecutorBusy => (StatusCode::SERVICE_UNAVAILABLE, None),
+        BunBunError::HopLoop(_) | BunBunError::HopTooDeep(_) => {
+            (StatusCode::LOOP_DETECTED, Some(format!("{e}\n")))
         }
-        RouteResolution::Unresolved => Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(boxed(Full::from("not found\n"))),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, None),
+    };
+
+    Response::builder().status(status).body(boxed(Full::from(
+        body.unwrap_or_else(|| String::from("Something went wrong :(\n")),
+    )))
+}
+
+/// Responds to an unresolvable hop with a list of similar routes. The list is
+/// rendered as JSON if the client asked for it, or as a HTML page otherwise.
+fn not_found(
+    query: &str,
+    routes: &RouteTrie,
+    handlebars: &Handlebars,
+    headers: &HeaderMap,
+) -> Result<Response, StatusCode> {
+    let suggestions = suggest_routes(query, routes);
+    let args = template_args::not_found(query, &suggestions);
+
+    let wants_json = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json"));
+
+    if wants_json {
+        return Ok((StatusCode::NOT_FOUND, Json(args)).into_response());
+    }
+
+    handlebars
+        .render("not_found", &args)
+        .map(|body| (StatusCode::NOT_FOUND, Html(body)).into_response())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// A route similar to what the user may have intended to hop to.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Suggestion<'a> {
+    keyword: String,
+    description: Option<&'a str>,
+    path: &'a str,
+    min_args: Option<usize>,
+    max_args: Option<usize>,
+    /// A human-readable summary of the number of arguments accepted.
+    arg_count: String,
+    /// A link that retries the hop with the suggested keyword.
+    href: String,
+}
+
+/// Describes the argument count range of a route in plain English.
+fn describe_arg_count(min_args: Option<usize>, max_args: Option<usize>) -> String {
+    match (min_args.unwrap_or_default(), max_args) {
+        (_, Some(0)) => String::from("none"),
+        (0, None) => String::from("any"),
+        (min, None) => format!("at least {min}"),
+        (0, Some(max)) => format!("at most {max}"),
+        (min, Some(max)) if min == max => format!("exactly {min}"),
+        (min, Some(max)) => format!("{min} to {max}"),
     }
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Finds the visible routes whose keywords most closely resemble the start of
+/// the query. A keyword is considered similar if the query's leading words are
+/// a prefix of it or are within a few edits of it. The remaining words of the
+/// query are kept as arguments for the suggestion's link.
+fn suggest_routes<'a>(query: &str, routes: &'a RouteTrie) -> Vec<Suggestion<'a>> {
+    let tokens = query.split_ascii_whitespace().collect::<Vec<_>>();
+
+    let mut candidates = routes
+        .routes()
+        .into_iter()
+        .filter(|(_, route)| !route.hidden)
+        .filter_map(|(keyword, route)| {
+            let typed_len = keyword.split_ascii_whitespace().count().min(tokens.len());
+            let typed = tokens[..typed_len].join(" ");
+            let distance = if keyword.starts_with(&typed) {
+                0
+            } else {
+                strsim::osa_distance(&typed, &keyword)
+            };
+
+            if distance > (keyword.len().max(typed.len()) / 3).max(1) {
+                return None;
+            }
+
+            let to = std::iter::once(keyword.as_str())
+                .chain(tokens[typed_len..].iter().copied())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let suggestion = Suggestion {
+                href: format!("/hop?to={}", utf8_percent_encode(&to, NON_ALPHANUMERIC)),
+                keyword,
+                description: route.description.as_deref(),
+                path: &route.path,
+                min_args: route.min_args,
+                max_args: route.max_args,
+                arg_count: describe_arg_count(route.min_args, route.max_args),
+            };
+            Some((distance, suggestion))
+        })
+        .collect::<Vec<_>>();
+
+    candidates
+        .sort_by(|(a_dist, a), (b_dist, b)| a_dist.cmp(b_dist).then(a.keyword.cmp(&b.keyword)));
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, suggestion)| suggestion)
+        .collect()
 }
 
 #[derive(Debug, PartialEq)]
 enum RouteResolution<'a> {
-    Resolved { route: &'a Route, args: String },
+    Resolved {
+        route: &'a Route,
+        /// The keyword the route was reached by, or None if it was reached by
+        /// a pattern.
+        keyword: Option<String>,
+        /// The pattern the route was reached by, if it wasn't reached by a
+        /// keyword.
+        pattern: Option<&'a Pattern>,
+        args: String,
+        /// The arguments as the user spaced and quoted them, which programs
+        /// may split differently than the whitespace `args` is split on.
+        raw_args: String,
+        /// Groups captured by the pattern that matched the query, if any.
+        captures: HashMap<String, String>,
+    },
     Unresolved,
 }
 
 /// Attempts to resolve the provided string into its route and its arguments.
-/// If a default route was provided, then this will consider that route before
-/// failing to resolve a route.
+/// If a bang sigil was provided, then any word starting with it is first tried
+/// as the keyword, regardless of its position in the query. Otherwise, keywords
+/// may span multiple words, in which case the longest keyword that
+/// prefixes the query and accepts the remaining arguments is used. If no
+/// keyword matched, then the patterns are tried in order, using the entire
+/// query as the arguments. Finally, the default routes are tried in order,
+/// again using the entire query as the arguments, before failing to resolve a
+/// route.
 ///
 /// The first element in the tuple describes the route, while the second element
 /// returns the remaining arguments. If none remain, an empty string is given.
 fn resolve_hop<'a>(
     query: &str,
-    routes: &'a HashMap<String, Route>,
-    default_route: Option<&str>,
+    routes: &'a RouteTrie,
+    patterns: &'a [PatternRoute],
+    bang_sigil: Option<&str>,
+    default_routes: &[String],
 ) -> RouteResolution<'a> {
-    let mut split_args = query.split_ascii_whitespace().peekable();
-    let maybe_route = if let Some(command) = split_args.peek() {
-        routes.get(*command)
-    } else {
+    let args = query.split_ascii_whitespace().collect::<Vec<_>>();
+    if args.is_empty() {
         debug!("Found empty query, returning no route.");
         return RouteResolution::Unresolved;
-    };
+    }
 
-    let args = split_args.collect::<Vec<_>>();
-    let arg_count = args.len();
+    // Try resolving with a bang, by moving its keyword to the front
+    if let Some(sigil) = bang_sigil.filter(|sigil| !sigil.is_empty()) {
+        for (i, arg) in args.iter().enumerate() {
+            let Some(keyword) = arg.strip_prefix(sigil).filter(|kw| !kw.is_empty()) else {
+                continue;
+            };
 
-    // Try resolving with a matched command
-    if let Some(route) = maybe_route {
-        let args = if args.is_empty() { &[] } else { &args[1..] }.join(" ");
-        let arg_count = arg_count - 1;
-        if check_route(route, arg_count) {
-            debug!("Resolved {route} with args {args}");
-            return RouteResolution::Resolved { route, args };
+            let reordered = std::iter::once(keyword)
+                .chain(args[..i].iter().copied())
+                .chain(args[i + 1..].iter().copied())
+                .collect::<Vec<_>>();
+            let start = arg.as_ptr() as usize - query.as_ptr() as usize;
+            let raw_reordered = [
+                keyword,
+                query[..start].trim_ascii(),
+                query[start + arg.len()..].trim_ascii(),
+            ]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+            if let Some(resolution) = resolve_keyword(&reordered, &raw_reordered, routes) {
+                debug!("Resolved bang {arg}");
+                return resolution;
+            }
         }
     }
 
-    // Try resolving with the default route, if it exists
-    if let Some(route) = default_route.and_then(|route| routes.get(route)) {
-        if check_route(route, arg_count) {
-            let args = args.join(" ");
-            debug!("Using default route {route} with args {args}");
-            return RouteResolution::Resolved { route, args };
+    // Try resolving with a matched command, preferring longer keywords
+    if let Some(resolution) = resolve_keyword(&args, query, routes) {
+        return resolution;
+    }
+
+    // Try resolving with the first matching pattern
+    let normalized_query = args.join(" ");
+    for PatternRoute { pattern, route } in patterns {
+        if let Some(captures) = pattern.captures(&normalized_query) {
+            if check_route(route, &args) {
+                debug!("Matched pattern {pattern}, using {route} with args {normalized_query}");
+                return RouteResolution::Resolved {
+                    route,
+                    keyword: None,
+                    pattern: Some(pattern),
+                    args: normalized_query,
+                    raw_args: query.trim_ascii().to_owned(),
+                    captures,
+                };
+            }
+        }
+    }
+
+    // Try resolving with the first default route that accepts the query
+    for (i, keyword) in default_routes.iter().enumerate() {
+        let Some(route) = routes.get(keyword) else {
+            trace!("Skipping fallback #{i}, no route is named {keyword}");
+            continue;
+        };
+
+        if check_route(route, &args) {
+            debug!("Using fallback #{i} ({keyword}) with args {normalized_query}");
+            return RouteResolution::Resolved {
+                route,
+                keyword: Some(
+                    keyword
+                        .split_ascii_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                pattern: None,
+                args: normalized_query,
+                raw_args: query.trim_ascii().to_owned(),
+                captures: HashMap::new(),
+            };
+        }
+
+        trace!("Skipping fallback #{i} ({keyword}), it rejected args {normalized_query}");
+    }
+
+    RouteResolution::Unresolved
+}
+
+/// Resolves the query like [`resolve_hop`], but if an alias route was resolved,
+/// then its path followed by the arguments is resolved instead. Aliases to
+/// aliases are followed as well, up to a limit so that cycles can't hang the
+/// request; hops that exceed it are considered unresolved.
+fn follow_aliases<'a>(
+    query: &str,
+    routes: &'a RouteTrie,
+    patterns: &'a [PatternRoute],
+    bang_sigil: Option<&str>,
+    default_routes: &[String],
+) -> RouteResolution<'a> {
+    let mut query = Cow::Borrowed(query);
+    for _ in 0..=MAX_ALIAS_DEPTH {
+        match resolve_hop(&query, routes, patterns, bang_sigil, default_routes) {
+            RouteResolution::Resolved {
+                route:
+                    ConfigRoute {
+                        route_type: RouteType::Alias,
+                        path,
+                        ..
+                    },
+                raw_args,
+                ..
+            } => {
+                debug!("Following alias to {path} with args {raw_args}");
+                query = Cow::Owned(format!("{path} {raw_args}"));
+            }
+            resolution => return resolution,
         }
     }
 
+    warn!("Stopped following aliases at {query}, as they were nested too deeply");
     RouteResolution::Unresolved
 }
 
+/// Attempts to resolve the words into the route with the longest keyword that
+/// prefixes them and accepts the remaining words as its arguments. The raw
+/// query must hold the same words, in the same order.
+fn resolve_keyword<'a>(
+    args: &[&str],
+    raw_query: &str,
+    routes: &'a RouteTrie,
+) -> Option<RouteResolution<'a>> {
+    for (route, keyword_len) in routes.prefixes(args) {
+        let (keyword, args) = args.split_at(keyword_len);
+        if check_route(route, args) {
+            let args = args.join(" ");
+            debug!("Resolved {route} with args {args}");
+            return Some(RouteResolution::Resolved {
+                route,
+                keyword: Some(keyword.join(" ")),
+                pattern: None,
+                args,
+                raw_args: skip_words(raw_query, keyword_len).to_owned(),
+                captures: HashMap::new(),
+            });
+        }
+    }
+
+    None
+}
+
+/// Returns the rest of the query after its first words, keeping its spacing.
+fn skip_words(query: &str, count: usize) -> &str {
+    (0..count).fold(query.trim_ascii(), |rest, _| {
+        rest.trim_start_matches(|c: char| !c.is_ascii_whitespace())
+            .trim_ascii_start()
+    })
+}
+
 /// Checks if the user provided string has the correct properties required by
 /// the route to be successfully matched.
-const fn check_route(route: &Route, arg_count: usize) -> bool {
+fn check_route(route: &Route, args: &[&str]) -> bool {
+    check_arg_count(route, args.len()) && route.bind_params(args).is_some()
+}
+
+/// Checks if the number of arguments is within the range accepted by the route.
+const fn check_arg_count(route: &Route, arg_count: usize) -> bool {
     if let Some(min_args) = route.min_args {
         if arg_count < min_args {
             return false;
@@ -203,50 +879,308 @@ const fn check_route(route: &Route, arg_count: usize) -> bool {
     true
 }
 
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+/// How a hop is answered.
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
 #[serde(rename_all = "snake_case")]
-enum HopAction<'a> {
+pub enum HopAction<'a> {
     Redirect(Cow<'a, str>),
     Body(String),
+    Html(String),
+    /// Another query to hop to instead.
+    Hop(String),
+}
+
+/// A hop action, alongside the rest of the response that carries it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HopResponse<'a> {
+    action: HopAction<'a>,
+    status: StatusCode,
+    headers: HeaderMap,
+}
+
+impl<'a> From<HopAction<'a>> for HopResponse<'a> {
+    fn from(action: HopAction<'a>) -> Self {
+        let mut headers = HeaderMap::new();
+        if matches!(action, HopAction::Html(_)) {
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/html; charset=utf-8"),
+            );
+        }
+
+        let status = match action {
+            HopAction::Redirect(_) => StatusCode::FOUND,
+            HopAction::Body(_) | HopAction::Html(_) | HopAction::Hop(_) => StatusCode::OK,
+        };
+
+        Self {
+            action,
+            status,
+            headers,
+        }
+    }
+}
+
+/// The JSON object executables respond with, before it's been validated.
+#[derive(Deserialize, Debug)]
+struct ProgramResponse {
+    #[serde(flatten)]
+    action: HopAction<'static>,
+    status: Option<u16>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    content_type: Option<String>,
+    redirect_kind: Option<u16>,
+}
+
+/// Parses and validates the output of an executable. Returns an error
+/// describing the problem if the output is malformed, such as when a field was
+/// given that doesn't apply to the action.
+fn parse_program_response(output: &[u8]) -> Result<HopResponse<'static>, BunBunError> {
+    let invalid = |reason: String| BunBunError::InvalidProgramOutput(reason);
+    let program_response: ProgramResponse =
+        serde_json::from_slice(output).map_err(|e| invalid(e.to_string()))?;
+    let is_redirect = matches!(program_response.action, HopAction::Redirect(_));
+    if matches!(program_response.action, HopAction::Hop(_))
+        && (program_response.status.is_some()
+            || !program_response.headers.is_empty()
+            || program_response.content_type.is_some())
+    {
+        return Err(invalid(
+            "hops can't have a status, headers or content_type".to_owned(),
+        ));
+    }
+    let mut response = HopResponse::from(program_response.action);
+
+    for (name, value) in program_response.headers {
+        let name = HeaderName::try_from(&name)
+            .map_err(|_| invalid(format!("{name:?} isn't a valid header name")))?;
+        if RESERVED_HEADERS.contains(&name.as_str()) {
+            return Err(invalid(format!("the {name} header can't be set")));
+        }
+        let value = HeaderValue::try_from(&value)
+            .map_err(|_| invalid(format!("{value:?} isn't a valid header value")))?;
+        response.headers.insert(name, value);
+    }
+
+    if let Some(content_type) = program_response.content_type {
+        if is_redirect {
+            return Err(invalid("redirects can't have a content_type".to_owned()));
+        }
+        let content_type = HeaderValue::try_from(&content_type)
+            .map_err(|_| invalid(format!("{content_type:?} isn't a valid content_type")))?;
+        response.headers.insert(header::CONTENT_TYPE, content_type);
+    }
+
+    if let Some(status) = program_response.status {
+        if is_redirect {
+            return Err(invalid(
+                "redirects can't have a status, use redirect_kind instead".to_owned(),
+            ));
+        }
+        response.status = StatusCode::from_u16(status)
+            .map_err(|_| invalid(format!("{status} isn't a valid status")))?;
+    }
+
+    if let Some(redirect_kind) = program_response.redirect_kind {
+        if !is_redirect {
+            return Err(invalid(
+                "only redirects can have a redirect_kind".to_owned(),
+            ));
+        }
+        response.status = match redirect_kind {
+            301 | 302 | 303 | 307 | 308 => {
+                StatusCode::from_u16(redirect_kind).expect("redirect status codes to be valid")
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "{redirect_kind} isn't one of 301, 302, 303, 307 or 308"
+                )))
+            }
+        };
+    }
+
+    Ok(response)
+}
+
+/// Information about a hop, which is given to executables of routes that opt
+/// into it.
+#[derive(Serialize, Debug)]
+struct ExecContext<'a> {
+    /// The keyword the route was reached by, if it wasn't reached by a pattern.
+    keyword: Option<&'a str>,
+    /// The query as the user provided it.
+    query: &'a str,
+    args: &'a [&'a str],
+    client_ip: IpAddr,
+    /// The values of the [`RELAYED_HEADERS`] the client sent.
+    headers: BTreeMap<&'static str, &'a str>,
+    public_address: &'a str,
+    version: &'static str,
+}
+
+impl<'a> ExecContext<'a> {
+    fn new(hop: &'a Hop<'a>) -> Self {
+        Self {
+            keyword: hop.keyword,
+            query: hop.query,
+            args: hop.split_args,
+            client_ip: hop.client.ip(),
+            headers: relayed_headers(hop.headers),
+            public_address: &hop.state.public_address,
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+
+    /// Returns the environment variables that describe the most important
+    /// fields of the context.
+    fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![
+            ("BUNBUN_QUERY", self.query.to_owned()),
+            ("BUNBUN_CLIENT_IP", self.client_ip.to_string()),
+            ("BUNBUN_PUBLIC_ADDRESS", self.public_address.to_owned()),
+            ("BUNBUN_VERSION", self.version.to_owned()),
+        ];
+        if let Some(keyword) = self.keyword {
+            vars.push(("BUNBUN_KEYWORD", keyword.to_owned()));
+        }
+        vars
+    }
+}
+
+/// Returns the values of the [`RELAYED_HEADERS`] that were sent, skipping any
+/// that aren't valid strings.
+fn relayed_headers(headers: &HeaderMap) -> BTreeMap<&'static str, &str> {
+    RELAYED_HEADERS
+        .iter()
+        .filter_map(|&name| Some((name, headers.get(name)?.to_str().ok()?)))
+        .collect()
 }
 
-/// Runs the executable with the user's input as a single argument. Returns Ok
-/// so long as the executable was successfully executed. Returns an Error if the
+/// Runs the executable with the provided arguments, writing the input to its
+/// stdin if there is any. Returns Ok so long as the executable was successfully
+/// executed. Returns an Error if the
 /// file doesn't exist or bunbun did not have permission to read and execute the
-/// file.
-fn resolve_path(path: &Path, args: &str) -> Result<HopAction<'static>, BunBunError> {
-    let output = Command::new(path.canonicalize()?)
-        .args(args.split(' '))
-        .output()?;
-
-    if output.status.success() {
-        Ok(serde_json::from_slice(&output.stdout)?)
+/// file, or if the executable exceeded its limits, in which case it's killed.
+/// Time spent waiting for the executor counts towards the timeout.
+///
+/// If context was provided, it's written to the executable's stdin as JSON
+/// instead of the input, and some of its fields are set as environment
+/// variables.
+async fn resolve_path(
+    path: &Path,
+    args: &[String],
+    input: Option<&str>,
+    limits: ExecLimits,
+    executor: &Executor,
+    sandbox: &Sandbox,
+    context: Option<&ExecContext<'_>>,
+) -> Result<HopResponse<'static>, BunBunError> {
+    let input = match context {
+        Some(context) => Some(serde_json::to_vec(context)?),
+        None => input.map(|input| input.as_bytes().to_vec()),
+    };
+    let run = async {
+        let _permit = executor.acquire().await?;
+        let mut command = Command::new(path.canonicalize()?);
+        command
+            .args(args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        sandbox::apply(&mut command, sandbox)?;
+        if let Some(context) = context {
+            command.envs(context.env_vars());
+        }
+        let mut child = command.spawn()?;
+
+        let stdin = write_input(child.stdin.take(), input.as_deref());
+        let stdout = read_capped(child.stdout.take(), limits.max_output_bytes);
+        let stderr = read_capped(child.stderr.take(), limits.max_output_bytes);
+        let status = async { child.wait().await.map_err(BunBunError::from) };
+        tokio::try_join!(stdin, stdout, stderr, status)
+    };
+
+    let ((), stdout, stderr, status) = timeout(limits.timeout, run)
+        .await
+        .map_err(|_| BunBunError::ProgramTimedOut(limits.timeout))??;
+
+    if status.success() {
+        parse_program_response(&stdout)
     } else {
         error!(
             "Program exit code for {} was not 0! Dumping standard error!",
             path.display(),
         );
-        let error = String::from_utf8_lossy(&output.stderr);
+        let error = String::from_utf8_lossy(&stderr);
         Err(BunBunError::CustomProgram(error.to_string()))
     }
 }
 
+/// Writes the input to the stream, if both exist, before closing it.
+/// Executables may exit without reading all of their input.
+async fn write_input(
+    stream: Option<impl AsyncWrite + Unpin>,
+    input: Option<&[u8]>,
+) -> Result<(), BunBunError> {
+    if let (Some(mut stream), Some(input)) = (stream, input) {
+        match stream.write_all(input).await {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the stream until it closes, failing if more than the provided number
+/// of bytes were written to it.
+async fn read_capped(
+    stream: Option<impl AsyncRead + Unpin>,
+    max_bytes: usize,
+) -> Result<Vec<u8>, BunBunError> {
+    let mut output = Vec::new();
+    if let Some(stream) = stream {
+        let limit = u64::try_from(max_bytes)
+            .unwrap_or(u64::MAX)
+            .saturating_add(1);
+        stream.take(limit).read_to_end(&mut output).await?;
+    }
+
+    if output.len() > max_bytes {
+        return Err(BunBunError::ProgramOutputTooLarge(max_bytes));
+    }
+    Ok(output)
+}
+
 #[cfg(test)]
 mod resolve_hop {
     use super::*;
-    use anyhow::Result;
+    use crate::test_utils::generate_routes;
 
-    fn generate_route_result<'a>(keyword: &'a Route, args: &str) -> RouteResolution<'a> {
+    fn generate_route_result<'a>(
+        route: &'a Route,
+        keyword: &str,
+        args: &str,
+    ) -> RouteResolution<'a> {
         RouteResolution::Resolved {
-            route: keyword,
+            route,
+            keyword: Some(String::from(keyword)),
+            pattern: None,
             args: String::from(args),
+            raw_args: String::from(args),
+            captures: HashMap::new(),
         }
     }
 
     #[test]
     fn empty_routes_no_default_yields_failed_hop() {
         assert_eq!(
-            resolve_hop("hello world", &HashMap::new(), None),
+            resolve_hop("hello world", &RouteTrie::default(), &[], None, &[]),
             RouteResolution::Unresolved
         );
     }
@@ -254,158 +1188,1168 @@ mod resolve_hop {
     #[test]
     fn empty_routes_some_default_yields_failed_hop() {
         assert_eq!(
-            resolve_hop("hello world", &HashMap::new(), Some(&"google")),
+            resolve_hop(
+                "hello world",
+                &RouteTrie::default(),
+                &[],
+                None,
+                &["google".into()]
+            ),
             RouteResolution::Unresolved
         );
     }
 
     #[test]
-    fn only_default_routes_some_default_yields_default_hop() -> Result<()> {
-        let mut map: HashMap<String, Route> = HashMap::new();
-        map.insert("google".into(), Route::from("https://example.com"));
+    fn only_default_routes_some_default_yields_default_hop() {
+        let map = generate_routes(&[("google", Route::from("https://example.com"))]);
         assert_eq!(
-            resolve_hop("hello world", &map, Some("google")),
-            generate_route_result(&Route::from("https://example.com"), "hello world"),
+            resolve_hop("hello world", &map, &[], None, &["google".into()]),
+            generate_route_result(&Route::from("https://example.com"), "google", "hello world"),
         );
-        Ok(())
     }
 
     #[test]
-    fn non_default_routes_some_default_yields_non_default_hop() -> Result<()> {
-        let mut map: HashMap<String, Route> = HashMap::new();
-        map.insert("google".into(), Route::from("https://example.com"));
+    fn non_default_routes_some_default_yields_non_default_hop() {
+        let map = generate_routes(&[("google", Route::from("https://example.com"))]);
         assert_eq!(
-            resolve_hop("google hello world", &map, Some("a")),
-            generate_route_result(&Route::from("https://example.com"), "hello world"),
+            resolve_hop("google hello world", &map, &[], None, &["a".into()]),
+            generate_route_result(&Route::from("https://example.com"), "google", "hello world"),
         );
-        Ok(())
     }
 
     #[test]
-    fn non_default_routes_no_default_yields_non_default_hop() -> Result<()> {
-        let mut map: HashMap<String, Route> = HashMap::new();
-        map.insert("google".into(), Route::from("https://example.com"));
+    fn non_default_routes_no_default_yields_non_default_hop() {
+        let map = generate_routes(&[("google", Route::from("https://example.com"))]);
         assert_eq!(
-            resolve_hop("google hello world", &map, None),
-            generate_route_result(&Route::from("https://example.com"), "hello world"),
+            resolve_hop("google hello world", &map, &[], None, &[]),
+            generate_route_result(&Route::from("https://example.com"), "google", "hello world"),
         );
-        Ok(())
     }
-}
-
-#[cfg(test)]
-mod check_route {
-    use super::*;
 
-    fn create_route(
-        min_args: impl Into<Option<usize>>,
-        max_args: impl Into<Option<usize>>,
-    ) -> Route {
-        Route {
-            description: None,
-            hidden: false,
-            max_args: max_args.into(),
-            min_args: min_args.into(),
-            path: String::new(),
-            route_type: RouteType::External,
-        }
+    #[test]
+    fn multi_word_keyword_prefers_longest_match() {
+        let map = generate_routes(&[
+            ("gh", Route::from("https://github.com")),
+            ("gh pr", Route::from("https://github.com/pulls")),
+        ]);
+        assert_eq!(
+            resolve_hop("gh pr 12", &map, &[], None, &[]),
+            generate_route_result(&Route::from("https://github.com/pulls"), "gh pr", "12"),
+        );
+        assert_eq!(
+            resolve_hop("gh issue 12", &map, &[], None, &[]),
+            generate_route_result(&Route::from("https://github.com"), "gh", "issue 12"),
+        );
     }
 
     #[test]
-    fn no_min_arg_no_max_arg_counts() {
-        assert!(check_route(&create_route(None, None), 0));
-        assert!(check_route(&create_route(None, None), usize::MAX));
+    fn multi_word_keyword_falls_back_to_shorter_match() {
+        let mut pulls = Route::from("https://github.com/pulls");
+        pulls.min_args = Some(1);
+        let map = generate_routes(&[("gh", Route::from("https://github.com")), ("gh pr", pulls)]);
+        assert_eq!(
+            resolve_hop("gh pr", &map, &[], None, &[]),
+            generate_route_result(&Route::from("https://github.com"), "gh", "pr"),
+        );
     }
 
     #[test]
-    fn min_arg_no_max_arg_counts() {
-        assert!(!check_route(&create_route(3, None), 0));
-        assert!(!check_route(&create_route(3, None), 2));
-        assert!(check_route(&create_route(3, None), 3));
-        assert!(check_route(&create_route(3, None), 4));
-        assert!(check_route(&create_route(3, None), usize::MAX));
+    fn multi_word_default_route() {
+        let map = generate_routes(&[("gh pr", Route::from("https://github.com/pulls"))]);
+        assert_eq!(
+            resolve_hop("hello world", &map, &[], None, &["gh  pr".into()]),
+            generate_route_result(
+                &Route::from("https://github.com/pulls"),
+                "gh pr",
+                "hello world"
+            ),
+        );
     }
 
-    #[test]
-    fn no_min_arg_max_arg_counts() {
-        assert!(check_route(&create_route(None, 3), 0));
-        assert!(check_route(&create_route(None, 3), 2));
-        assert!(check_route(&create_route(None, 3), 3));
-        assert!(!check_route(&create_route(None, 3), 4));
-        assert!(!check_route(&create_route(None, 3), usize::MAX));
+    fn generate_patterns(patterns: &[(&str, &'static str)]) -> Vec<PatternRoute> {
+        patterns
+            .iter()
+            .map(|(pattern, path)| PatternRoute {
+                pattern: serde_yaml::from_str(&format!("'{pattern}'")).unwrap(),
+                route: Route::from(*path),
+            })
+            .collect()
     }
 
     #[test]
-    fn min_arg_max_arg_counts() {
-        assert!(!check_route(&create_route(2, 3), 1));
-        assert!(check_route(&create_route(2, 3), 2));
-        assert!(check_route(&create_route(2, 3), 3));
-        assert!(!check_route(&create_route(2, 3), 4));
+    fn pattern_matches_whole_query() {
+        let patterns = generate_patterns(&[(r"(?P<project>[A-Z]+)-(\d+)", "https://jira")]);
+        assert_eq!(
+            resolve_hop(" PROJ-123 ", &RouteTrie::default(), &patterns, None, &[]),
+            RouteResolution::Resolved {
+                route: &Route::from("https://jira"),
+                keyword: None,
+                pattern: Some(&patterns[0].pattern),
+                args: String::from("PROJ-123"),
+                raw_args: String::from("PROJ-123"),
+                captures: HashMap::from([
+                    (String::from("0"), String::from("PROJ-123")),
+                    (String::from("1"), String::from("PROJ")),
+                    (String::from("project"), String::from("PROJ")),
+                    (String::from("2"), String::from("123")),
+                ]),
+            }
+        );
+        assert_eq!(
+            resolve_hop("PROJ-123 a", &RouteTrie::default(), &patterns, None, &[]),
+            RouteResolution::Unresolved
+        );
     }
-}
-
-#[cfg(test)]
-mod resolve_path {
-    use crate::error::BunBunError;
-
-    use super::{resolve_path, HopAction};
-    use anyhow::Result;
-    use std::borrow::Cow;
-    use std::env::current_dir;
-    use std::io::ErrorKind;
-    use std::path::{Path, PathBuf};
 
     #[test]
-    fn invalid_path_returns_err() {
-        assert!(resolve_path(&Path::new("/bin/aaaa"), "aaaa").is_err());
+    fn patterns_are_tried_in_order() {
+        let patterns = generate_patterns(&[(r"\d+", "1"), (r"[0-9a-f]+", "2"), (r"\d+", "3")]);
+        let routes = RouteTrie::default();
+        assert!(matches!(
+            resolve_hop("123", &routes, &patterns, None, &[]),
+            RouteResolution::Resolved { route, .. } if route == &Route::from("1")
+        ));
+        assert!(matches!(
+            resolve_hop("abc", &routes, &patterns, None, &[]),
+            RouteResolution::Resolved { route, .. } if route == &Route::from("2")
+        ));
     }
 
     #[test]
-    fn valid_path_returns_ok() {
-        assert!(resolve_path(&Path::new("/bin/echo"), r#"{"body": "a"}"#).is_ok());
+    fn keywords_take_priority_over_patterns() {
+        let map = generate_routes(&[("123", Route::from("https://example.com"))]);
+        let patterns = generate_patterns(&[(r"\d+", "1")]);
+        assert_eq!(
+            resolve_hop("123", &map, &patterns, None, &[]),
+            generate_route_result(&Route::from("https://example.com"), "123", ""),
+        );
     }
 
     #[test]
-    fn relative_path_returns_ok() -> Result<()> {
-        // How many ".." needed to get to /
-        let nest_level = current_dir()?.ancestors().count() - 1;
-        let mut rel_path = PathBuf::from("../".repeat(nest_level));
-        rel_path.push("./bin/echo");
-        assert!(resolve_path(&rel_path, r#"{"body": "a"}"#).is_ok());
-        Ok(())
+    fn bangs_resolve_in_any_position() {
+        let map = generate_routes(&[
+            ("g", Route::from("https://google.com")),
+            ("w", Route::from("https://wikipedia.org")),
+        ]);
+        for query in ["!w hello world", "hello !w world", "hello world !w"] {
+            assert_eq!(
+                resolve_hop(query, &map, &[], Some("!"), &["g".into()]),
+                generate_route_result(&Route::from("https://wikipedia.org"), "w", "hello world"),
+            );
+        }
     }
 
     #[test]
-    fn no_permissions_returns_err() {
-        let result = match resolve_path(&Path::new("/root/some_exec"), "") {
-            Err(BunBunError::Io(e)) => e.kind() == ErrorKind::PermissionDenied,
-            _ => false,
-        };
-        assert!(result);
+    fn raw_args_keep_spacing_and_quotes() {
+        let map = generate_routes(&[
+            ("g", Route::from("https://google.com")),
+            ("gh pr", Route::from("https://github.com/pulls")),
+        ]);
+        let patterns = generate_patterns(&[(r"\d+ .*", "https://jira")]);
+        for (query, bang_sigil, raw) in [
+            ("gh  pr  'a  b'  c ", None, "'a  b'  c"),
+            ("'a  b'  !g  c", Some("!"), "'a  b' c"),
+            (" 12  'a  b' ", None, "12  'a  b'"),
+            ("a  b", None, "a  b"),
+        ] {
+            assert!(matches!(
+                resolve_hop(query, &map, &patterns, bang_sigil, &["g".into()]),
+                RouteResolution::Resolved { raw_args, .. } if raw_args == raw
+            ));
+        }
     }
 
     #[test]
-    fn non_success_exit_code_yields_err() {
-        // cat-ing a folder always returns exit code 1
-        assert!(resolve_path(&Path::new("/bin/cat"), "/").is_err());
+    fn bangs_take_priority_over_keywords() {
+        let map = generate_routes(&[
+            ("g", Route::from("https://google.com")),
+            ("w", Route::from("https://wikipedia.org")),
+        ]);
+        assert_eq!(
+            resolve_hop("g hello !w", &map, &[], Some("!"), &[]),
+            generate_route_result(&Route::from("https://wikipedia.org"), "w", "g hello"),
+        );
     }
 
     #[test]
-    fn return_body() -> Result<()> {
+    fn bangs_support_multi_word_keywords() {
+        let map = generate_routes(&[
+            ("gh", Route::from("https://github.com")),
+            ("gh pr", Route::from("https://github.com/pulls")),
+        ]);
         assert_eq!(
-            resolve_path(&Path::new("/bin/echo"), r#"{"body": "a"}"#)?,
-            HopAction::Body("a".to_owned())
+            resolve_hop("!gh pr 12", &map, &[], Some("!"), &[]),
+            generate_route_result(&Route::from("https://github.com/pulls"), "gh pr", "12"),
+        );
+    }
+
+    #[test]
+    fn unknown_bangs_are_plain_text() {
+        let map = generate_routes(&[("g", Route::from("https://google.com"))]);
+        assert_eq!(
+            resolve_hop("hello !x", &map, &[], Some("!"), &["g".into()]),
+            generate_route_result(&Route::from("https://google.com"), "g", "hello !x"),
+        );
+        assert_eq!(
+            resolve_hop("hello !", &map, &[], Some("!"), &["g".into()]),
+            generate_route_result(&Route::from("https://google.com"), "g", "hello !"),
+        );
+    }
+
+    #[test]
+    fn bangs_are_disabled_without_sigil() {
+        let map = generate_routes(&[
+            ("g", Route::from("https://google.com")),
+            ("w", Route::from("https://wikipedia.org")),
+        ]);
+        assert_eq!(
+            resolve_hop("hello !w", &map, &[], None, &["g".into()]),
+            generate_route_result(&Route::from("https://google.com"), "g", "hello !w"),
+        );
+        assert_eq!(
+            resolve_hop("hello !w", &map, &[], Some(""), &["g".into()]),
+            generate_route_result(&Route::from("https://google.com"), "g", "hello !w"),
+        );
+    }
+
+    #[test]
+    fn default_routes_are_tried_in_order() {
+        let map = generate_routes(&[
+            (
+                "one",
+                Route {
+                    max_args: Some(1),
+                    ..Route::from("1")
+                },
+            ),
+            ("any", Route::from("2")),
+        ]);
+        let fallbacks = ["missing".into(), "one".into(), "any".into()];
+        assert!(matches!(
+            resolve_hop("hello", &map, &[], None, &fallbacks),
+            RouteResolution::Resolved { route, .. } if route.path == "1"
+        ));
+        assert_eq!(
+            resolve_hop("hello world", &map, &[], None, &fallbacks),
+            generate_route_result(&Route::from("2"), "any", "hello world"),
+        );
+    }
+
+    #[test]
+    fn all_default_routes_rejecting_yields_failed_hop() {
+        let map = generate_routes(&[(
+            "one",
+            Route {
+                max_args: Some(1),
+                ..Route::from("1")
+            },
+        )]);
+        assert_eq!(
+            resolve_hop(
+                "hello world",
+                &map,
+                &[],
+                None,
+                &["one".into(), "missing".into()]
+            ),
+            RouteResolution::Unresolved
+        );
+    }
+
+    #[test]
+    fn patterns_take_priority_over_default_route() {
+        let map = generate_routes(&[("google", Route::from("https://example.com"))]);
+        let patterns = generate_patterns(&[(r"\d+", "1")]);
+        assert!(matches!(
+            resolve_hop("123", &map, &patterns, None, &["google".into()]),
+            RouteResolution::Resolved { route, .. } if route == &Route::from("1")
+        ));
+        assert_eq!(
+            resolve_hop("abc", &map, &patterns, None, &["google".into()]),
+            generate_route_result(&Route::from("https://example.com"), "google", "abc"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod follow_aliases {
+    use super::*;
+    use crate::config::RouteType;
+    use crate::test_utils::generate_routes;
+
+    fn alias(path: &'static str) -> Route {
+        Route {
+            route_type: RouteType::Alias,
+            ..Route::from(path)
+        }
+    }
+
+    #[test]
+    fn follows_alias_with_args() {
+        let map = generate_routes(&[
+            ("gh pr", Route::from("https://github.com/pulls")),
+            ("pr", alias("gh  pr")),
+        ]);
+        assert!(matches!(
+            follow_aliases("pr 12", &map, &[], None, &[]),
+            RouteResolution::Resolved { route, args, .. }
+                if route.path == "https://github.com/pulls" && args == "12"
+        ));
+    }
+
+    #[test]
+    fn follows_alias_with_raw_args() {
+        let map = generate_routes(&[("g", Route::from("https://google.com")), ("a", alias("g"))]);
+        assert!(matches!(
+            follow_aliases("a  'b  c'", &map, &[], None, &[]),
+            RouteResolution::Resolved { args, raw_args, .. }
+                if args == "'b c'" && raw_args == "'b  c'"
+        ));
+    }
+
+    #[test]
+    fn follows_nested_aliases() {
+        let map = generate_routes(&[
+            ("g", Route::from("https://google.com")),
+            ("a", alias("g")),
+            ("b", alias("a")),
+        ]);
+        assert!(matches!(
+            follow_aliases("b hello", &map, &[], None, &[]),
+            RouteResolution::Resolved { route, args, .. }
+                if route.path == "https://google.com" && args == "hello"
+        ));
+    }
+
+    #[test]
+    fn alias_cycles_are_unresolved() {
+        let map = generate_routes(&[("a", alias("b")), ("b", alias("a"))]);
+        assert_eq!(
+            follow_aliases("a hello", &map, &[], None, &[]),
+            RouteResolution::Unresolved
+        );
+    }
+}
+
+#[cfg(test)]
+mod rehop {
+    use super::*;
+
+    #[test]
+    fn replaces_query() -> anyhow::Result<()> {
+        let mut query = String::from("a");
+        let mut previous_queries = Vec::new();
+        rehop(&mut query, &mut previous_queries, " b  c ")?;
+        assert_eq!(query, "b c");
+        assert_eq!(previous_queries, ["a"]);
+        Ok(())
+    }
+
+    #[test]
+    fn loops_fail() {
+        let mut query = String::from("c");
+        let mut previous_queries = vec![String::from("a"), String::from("b")];
+        assert!(matches!(
+            rehop(&mut query, &mut previous_queries, "b"),
+            Err(BunBunError::HopLoop(chain)) if chain == ["a", "b", "c", "b"]
+        ));
+        assert!(matches!(
+            rehop(&mut query, &mut previous_queries, "c"),
+            Err(BunBunError::HopLoop(_))
+        ));
+    }
+
+    #[test]
+    fn deep_chains_fail() {
+        let mut query = String::from("0");
+        let mut previous_queries = Vec::new();
+        for i in 1..=MAX_REHOP_DEPTH {
+            assert!(rehop(&mut query, &mut previous_queries, &i.to_string()).is_ok());
+        }
+        assert!(matches!(
+            rehop(&mut query, &mut previous_queries, "next"),
+            Err(BunBunError::HopTooDeep(MAX_REHOP_DEPTH))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod suggest_routes {
+    use super::*;
+    use crate::test_utils::generate_routes;
+
+    fn keywords(suggestions: &[Suggestion]) -> Vec<String> {
+        suggestions
+            .iter()
+            .map(|suggestion| suggestion.keyword.clone())
+            .collect()
+    }
+
+    #[test]
+    fn empty_routes_yield_no_suggestions() {
+        assert!(suggest_routes("hello world", &RouteTrie::default()).is_empty());
+    }
+
+    #[test]
+    fn typos_are_suggested() {
+        let map = generate_routes(&[
+            ("google", Route::from("https://google.com")),
+            ("yt", Route::from("https://youtube.com")),
+        ]);
+        assert_eq!(keywords(&suggest_routes("gogle hello", &map)), ["google"]);
+        assert_eq!(keywords(&suggest_routes("ty hello", &map)), ["yt"]);
+    }
+
+    #[test]
+    fn prefixes_are_suggested_before_typos() {
+        let map = generate_routes(&[
+            ("gao", Route::from("https://example.com")),
+            ("google", Route::from("https://google.com")),
+        ]);
+        assert_eq!(keywords(&suggest_routes("goo", &map)), ["google", "gao"]);
+    }
+
+    #[test]
+    fn unrelated_routes_are_not_suggested() {
+        let map = generate_routes(&[("google", Route::from("https://google.com"))]);
+        assert!(suggest_routes("reddit hello", &map).is_empty());
+    }
+
+    #[test]
+    fn hidden_routes_are_not_suggested() {
+        let mut route = Route::from("https://google.com");
+        route.hidden = true;
+        let map = generate_routes(&[("google", route)]);
+        assert!(suggest_routes("gogle", &map).is_empty());
+    }
+
+    #[test]
+    fn multi_word_keywords_are_suggested() {
+        let map = generate_routes(&[("gh pr", Route::from("https://github.com/pulls"))]);
+        assert_eq!(keywords(&suggest_routes("gh pt 12", &map)), ["gh pr"]);
+    }
+
+    #[test]
+    fn suggestions_link_to_hop_with_args() {
+        let map = generate_routes(&[("google", Route::from("https://google.com"))]);
+        let suggestions = suggest_routes("gogle hello world&", &map);
+        assert_eq!(suggestions[0].href, "/hop?to=google%20hello%20world%26");
+    }
+
+    #[test]
+    fn arg_counts_are_described() {
+        assert_eq!(describe_arg_count(None, None), "any");
+        assert_eq!(describe_arg_count(Some(0), Some(0)), "none");
+        assert_eq!(describe_arg_count(Some(2), None), "at least 2");
+        assert_eq!(describe_arg_count(None, Some(2)), "at most 2");
+        assert_eq!(describe_arg_count(Some(2), Some(2)), "exactly 2");
+        assert_eq!(describe_arg_count(Some(1), Some(2)), "1 to 2");
+    }
+
+    #[test]
+    fn suggestions_are_capped() {
+        let map = generate_routes(&[
+            ("a1", Route::from("1")),
+            ("a2", Route::from("2")),
+            ("a3", Route::from("3")),
+            ("a4", Route::from("4")),
+            ("a5", Route::from("5")),
+            ("a6", Route::from("6")),
+        ]);
+        assert_eq!(suggest_routes("a", &map).len(), MAX_SUGGESTIONS);
+    }
+}
+
+#[cfg(test)]
+mod check_arg_count {
+    use super::*;
+    use crate::encoding::Encoding;
+    use std::collections::HashMap;
+
+    fn create_route(
+        min_args: impl Into<Option<usize>>,
+        max_args: impl Into<Option<usize>>,
+    ) -> Route {
+        Route {
+            aliases: Vec::new(),
+            description: None,
+            hidden: false,
+            max_args: max_args.into(),
+            min_args: min_args.into(),
+            params: Vec::new(),
+            path: String::new(),
+            encoding: Encoding::default(),
+            command: None,
+            args_mode: ArgsMode::default(),
+            timeout_ms: None,
+            max_output_bytes: None,
+            context: false,
+            pool_size: None,
+            cache_ttl: None,
+            sandbox: Sandbox::default(),
+            script: None,
+            headers: BTreeMap::new(),
+            fallback: None,
+            urls: Vec::new(),
+            routes: HashMap::new(),
+            route_type: RouteType::External,
+            inferred_type: false,
+        }
+    }
+
+    #[test]
+    fn no_min_arg_no_max_arg_counts() {
+        assert!(check_arg_count(&create_route(None, None), 0));
+        assert!(check_arg_count(&create_route(None, None), usize::MAX));
+    }
+
+    #[test]
+    fn min_arg_no_max_arg_counts() {
+        assert!(!check_arg_count(&create_route(3, None), 0));
+        assert!(!check_arg_count(&create_route(3, None), 2));
+        assert!(check_arg_count(&create_route(3, None), 3));
+        assert!(check_arg_count(&create_route(3, None), 4));
+        assert!(check_arg_count(&create_route(3, None), usize::MAX));
+    }
+
+    #[test]
+    fn no_min_arg_max_arg_counts() {
+        assert!(check_arg_count(&create_route(None, 3), 0));
+        assert!(check_arg_count(&create_route(None, 3), 2));
+        assert!(check_arg_count(&create_route(None, 3), 3));
+        assert!(!check_arg_count(&create_route(None, 3), 4));
+        assert!(!check_arg_count(&create_route(None, 3), usize::MAX));
+    }
+
+    #[test]
+    fn min_arg_max_arg_counts() {
+        assert!(!check_arg_count(&create_route(2, 3), 1));
+        assert!(check_arg_count(&create_route(2, 3), 2));
+        assert!(check_arg_count(&create_route(2, 3), 3));
+        assert!(!check_arg_count(&create_route(2, 3), 4));
+    }
+}
+
+#[cfg(test)]
+mod check_route {
+    use super::*;
+    use serde_yaml::from_str;
+
+    #[test]
+    fn params_and_arg_counts_are_checked() -> anyhow::Result<()> {
+        let route = from_str::<Route>(
+            r#"
+            path: "https://jira/browse/{{ticket}}"
+            max_args: 1
+            params:
+              - name: ticket
+                pattern: "[A-Z]+-\\d+"
+            "#,
+        )?;
+        assert!(check_route(&route, &["PROJ-1"]));
+        assert!(!check_route(&route, &["proj-1"]));
+        assert!(!check_route(&route, &[]));
+        assert!(!check_route(&route, &["PROJ-1", "PROJ-2"]));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod static_file_type {
+    use super::*;
+
+    #[test]
+    fn guessed_from_extension() {
+        assert_eq!(
+            static_file_type(Path::new("/srv/index.HTML")),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(static_file_type(Path::new("data.json")), "application/json");
+        assert_eq!(
+            static_file_type(Path::new("notes")),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(
+            static_file_type(Path::new("archive.tar.gz")),
+            "text/plain; charset=utf-8"
+        );
+    }
+}
+
+#[cfg(test)]
+mod cache_key {
+    use super::*;
+    use serde_yaml::from_str;
+
+    fn context<'a>(keyword: Option<&'a str>, client_ip: &str) -> ExecContext<'a> {
+        query_context(keyword, "a b", client_ip)
+    }
+
+    fn query_context<'a>(
+        keyword: Option<&'a str>,
+        query: &'a str,
+        client_ip: &str,
+    ) -> ExecContext<'a> {
+        ExecContext {
+            keyword,
+            query,
+            args: &["b"],
+            client_ip: client_ip.parse().unwrap(),
+            headers: BTreeMap::new(),
+            public_address: "localhost",
+            version: "0",
+        }
+    }
+
+    #[test]
+    fn routes_sharing_a_path_are_cached_apart() -> anyhow::Result<()> {
+        let a = from_str::<Route>("path: /bin/lookup\ntype: exec")?;
+        let b = from_str::<Route>("command: [/bin/lookup, -a]\ntype: exec")?;
+        assert_ne!(
+            cache_key(&a, None, &context(Some("a"), "127.0.0.1"))?,
+            cache_key(&b, None, &context(Some("b"), "127.0.0.1"))?
+        );
+
+        let pattern = from_str::<Pattern>("'b'")?;
+        assert_ne!(
+            cache_key(&a, None, &context(Some("a"), "127.0.0.1"))?,
+            cache_key(&a, Some(&pattern), &context(None, "127.0.0.1"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn query_spacing_is_part_of_the_key() -> anyhow::Result<()> {
+        let route = from_str::<Route>("path: /bin/lookup\ntype: exec\nargs_mode: single")?;
+        assert_ne!(
+            cache_key(
+                &route,
+                None,
+                &query_context(Some("a"), "a 'b c'", "127.0.0.1")
+            )?,
+            cache_key(
+                &route,
+                None,
+                &query_context(Some("a"), "a 'b  c'", "127.0.0.1")
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn context_is_part_of_the_key() -> anyhow::Result<()> {
+        let route = from_str::<Route>("path: /bin/lookup\ntype: exec")?;
+        assert_eq!(
+            cache_key(&route, None, &context(Some("a"), "127.0.0.1"))?,
+            cache_key(&route, None, &context(Some("a"), "127.0.0.2"))?
+        );
+
+        let route = from_str::<Route>("path: /bin/lookup\ntype: worker")?;
+        assert_eq!(
+            cache_key(&route, None, &context(Some("a"), "127.0.0.1"))?,
+            cache_key(&route, None, &context(Some("a"), "127.0.0.2"))?
+        );
+
+        let route = from_str::<Route>("path: /bin/lookup\ntype: exec\ncontext: true")?;
+        assert_ne!(
+            cache_key(&route, None, &context(Some("a"), "127.0.0.1"))?,
+            cache_key(&route, None, &context(Some("a"), "127.0.0.2"))?
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod parse_program_response {
+    use super::*;
+
+    fn parse(output: &str) -> Result<HopResponse<'static>, BunBunError> {
+        parse_program_response(output.as_bytes())
+    }
+
+    #[test]
+    fn defaults_depend_on_action() -> anyhow::Result<()> {
+        let redirect = parse(r#"{"redirect": "a"}"#)?;
+        assert_eq!(redirect.status, StatusCode::FOUND);
+        assert!(redirect.headers.is_empty());
+
+        let body = parse(r#"{"body": "a"}"#)?;
+        assert_eq!(body.status, StatusCode::OK);
+        assert!(body.headers.is_empty());
+
+        let html = parse(r#"{"html": "a"}"#)?;
+        assert_eq!(html.status, StatusCode::OK);
+        assert_eq!(
+            html.headers[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
         );
+        Ok(())
+    }
 
+    #[test]
+    fn redirect_kind() -> anyhow::Result<()> {
+        for kind in [301, 302, 303, 307, 308] {
+            let response = parse(&format!(r#"{{"redirect": "a", "redirect_kind": {kind}}}"#))?;
+            assert_eq!(response.status.as_u16(), kind);
+        }
+        assert!(parse(r#"{"redirect": "a", "redirect_kind": 200}"#).is_err());
+        assert!(parse(r#"{"redirect": "a", "redirect_kind": 304}"#).is_err());
+        assert!(parse(r#"{"body": "a", "redirect_kind": 301}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn status() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(r#"{"body": "a", "status": 404}"#)?.status,
+            StatusCode::NOT_FOUND
+        );
+        assert!(parse(r#"{"body": "a", "status": 42}"#).is_err());
+        assert!(parse(r#"{"redirect": "a", "status": 301}"#).is_err());
         Ok(())
     }
 
     #[test]
-    fn return_redirect() -> Result<()> {
+    fn content_type_overrides_default() -> anyhow::Result<()> {
+        let response = parse(r#"{"html": "a", "content_type": "application/xhtml+xml"}"#)?;
         assert_eq!(
-            resolve_path(&Path::new("/bin/echo"), r#"{"redirect": "a"}"#)?,
+            response.headers[header::CONTENT_TYPE],
+            "application/xhtml+xml"
+        );
+        assert!(parse(r#"{"redirect": "a", "content_type": "text/plain"}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_headers_fail() {
+        assert!(parse(r#"{"body": "a", "headers": {"a b": "c"}}"#).is_err());
+        assert!(parse(r#"{"body": "a", "headers": {"a": "b\nc"}}"#).is_err());
+    }
+
+    #[test]
+    fn framing_headers_fail() {
+        for name in [
+            "Content-Length",
+            "transfer-encoding",
+            "connection",
+            "keep-alive",
+        ] {
+            assert!(matches!(
+                parse(&format!(r#"{{"body": "a", "headers": {{"{name}": "1"}}}}"#)),
+                Err(BunBunError::InvalidProgramOutput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn location_header_fails() {
+        assert!(matches!(
+            parse(r#"{"redirect": "a", "headers": {"Location": "b"}}"#),
+            Err(BunBunError::InvalidProgramOutput(_))
+        ));
+        assert!(matches!(
+            parse(r#"{"body": "a", "headers": {"location": "b"}}"#),
+            Err(BunBunError::InvalidProgramOutput(_))
+        ));
+    }
+
+    #[test]
+    fn hops_are_only_queries() -> anyhow::Result<()> {
+        assert_eq!(
+            parse(r#"{"hop": "g a"}"#)?.action,
+            HopAction::Hop("g a".to_owned())
+        );
+        assert!(parse(r#"{"hop": "g a", "status": 200}"#).is_err());
+        assert!(parse(r#"{"hop": "g a", "headers": {"a": "b"}}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn missing_action_fails() {
+        assert!(parse("{}").is_err());
+        assert!(parse(r#"{"status": 200}"#).is_err());
+        assert!(parse(r#"{"page": "a"}"#).is_err());
+    }
+}
+
+#[cfg(test)]
+mod resolve_path {
+    use crate::error::BunBunError;
+
+    use super::{resolve_path, ExecContext, HopAction};
+    use crate::config::Sandbox;
+    use crate::executor::ExecLimits;
+    use crate::test_utils::{executor, limits, script};
+    use anyhow::Result;
+    use axum::http::StatusCode;
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+    use std::env::current_dir;
+    use std::io::ErrorKind;
+    use std::net::IpAddr;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    fn split(args: &str) -> Vec<String> {
+        args.split_ascii_whitespace().map(String::from).collect()
+    }
+
+    #[tokio::test]
+    async fn invalid_path_returns_err() {
+        assert!(resolve_path(
+            Path::new("/bin/aaaa"),
+            &split("aaaa"),
+            None,
+            limits(),
+            &executor(),
+            &Sandbox::default(),
+            None
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn valid_path_returns_ok() {
+        assert!(resolve_path(
+            Path::new("/bin/echo"),
+            &split(r#"{"body": "a"}"#),
+            None,
+            limits(),
+            &executor(),
+            &Sandbox::default(),
+            None
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn relative_path_returns_ok() -> Result<()> {
+        // How many ".." needed to get to /
+        let nest_level = current_dir()?.ancestors().count() - 1;
+        let mut rel_path = PathBuf::from("../".repeat(nest_level));
+        rel_path.push("./bin/echo");
+        assert!(resolve_path(
+            &rel_path,
+            &split(r#"{"body": "a"}"#),
+            None,
+            limits(),
+            &executor(),
+            &Sandbox::default(),
+            None
+        )
+        .await
+        .is_ok());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn no_permissions_returns_err() {
+        let result = match resolve_path(
+            Path::new("/root/some_exec"),
+            &[],
+            None,
+            limits(),
+            &executor(),
+            &Sandbox::default(),
+            None,
+        )
+        .await
+        {
+            Err(BunBunError::Io(e)) => e.kind() == ErrorKind::PermissionDenied,
+            _ => false,
+        };
+        assert!(result);
+    }
+
+    #[tokio::test]
+    async fn non_success_exit_code_yields_err() {
+        // cat-ing a folder always returns exit code 1
+        assert!(resolve_path(
+            Path::new("/bin/cat"),
+            &split("/"),
+            None,
+            limits(),
+            &executor(),
+            &Sandbox::default(),
+            None
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn return_body() -> Result<()> {
+        assert_eq!(
+            resolve_path(
+                Path::new("/bin/echo"),
+                &split(r#"{"body": "a"}"#),
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await?
+            .action,
+            HopAction::Body("a".to_owned())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn return_redirect() -> Result<()> {
+        assert_eq!(
+            resolve_path(
+                Path::new("/bin/echo"),
+                &split(r#"{"redirect": "a"}"#),
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await?
+            .action,
             HopAction::Redirect(Cow::Borrowed("a"))
         );
         Ok(())
     }
+
+    #[tokio::test]
+    async fn return_full_response() -> Result<()> {
+        let response = resolve_path(
+            Path::new("/bin/echo"),
+            &split(r#"{"html": "<p>a</p>", "status": 418, "headers": {"x-a": "b"}}"#),
+            None,
+            limits(),
+            &executor(),
+            &Sandbox::default(),
+            None,
+        )
+        .await?;
+        assert_eq!(response.action, HopAction::Html("<p>a</p>".to_owned()));
+        assert_eq!(response.status, StatusCode::IM_A_TEAPOT);
+        assert_eq!(response.headers["x-a"], "b");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn malformed_output_yields_err() {
+        assert!(matches!(
+            resolve_path(
+                Path::new("/bin/echo"),
+                &split("a"),
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await,
+            Err(BunBunError::InvalidProgramOutput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn slow_program_times_out() {
+        let start = Instant::now();
+        let limits = ExecLimits {
+            timeout: Duration::from_millis(100),
+            ..limits()
+        };
+        assert!(matches!(
+            resolve_path(
+                Path::new("/bin/sleep"),
+                &split("5"),
+                None,
+                limits,
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await,
+            Err(BunBunError::ProgramTimedOut(_))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn large_output_yields_err() {
+        let limits = ExecLimits {
+            max_output_bytes: 4,
+            ..limits()
+        };
+        assert!(matches!(
+            resolve_path(
+                Path::new("/bin/echo"),
+                &split(r#"{"body": "a"}"#),
+                None,
+                limits,
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await,
+            Err(BunBunError::ProgramOutputTooLarge(4))
+        ));
+    }
+
+    #[tokio::test]
+    async fn endless_output_yields_err() {
+        assert!(matches!(
+            resolve_path(
+                Path::new("/usr/bin/yes"),
+                &[],
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await,
+            Err(BunBunError::ProgramOutputTooLarge(_))
+        ));
+    }
+
+    fn context<'a>(args: &'a [&'a str]) -> ExecContext<'a> {
+        ExecContext {
+            keyword: Some("gh pr"),
+            query: "gh pr 12",
+            args,
+            client_ip: IpAddr::from([127, 0, 0, 1]),
+            headers: BTreeMap::from([("user-agent", "curl")]),
+            public_address: "localhost:8080",
+            version: "1.0.0",
+        }
+    }
+
+    #[tokio::test]
+    async fn args_are_passed_as_is() -> Result<()> {
+        let script = script(r#"printf '{"body": "%s"}' "$#:$1""#)?;
+        let args = [String::from("a b"), String::new()];
+        assert_eq!(
+            resolve_path(
+                &script,
+                &args,
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await?
+            .action,
+            HopAction::Body("2:a b".to_owned())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn input_is_written_to_stdin() -> Result<()> {
+        let script = script(r#"printf '{"body": "%s"}' "$(cat)""#)?;
+        assert_eq!(
+            resolve_path(
+                &script,
+                &[],
+                Some("a b"),
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await?
+            .action,
+            HopAction::Body("a b".to_owned())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn context_is_written_to_stdin() -> Result<()> {
+        let script = script(
+            r#"printf '{"body": "%s"}' "$(grep -o '"keyword":"[^"]*","query":"[^"]*"' | tr -d '"')""#,
+        )?;
+        let args = ["12"];
+        assert_eq!(
+            resolve_path(
+                &script,
+                &split("12"),
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                Some(&context(&args))
+            )
+            .await?
+            .action,
+            HopAction::Body("keyword:gh pr,query:gh pr 12".to_owned())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn context_is_set_in_env() -> Result<()> {
+        let script = script(
+            r#"printf '{"body": "%s %s %s"}' "$BUNBUN_KEYWORD" "$BUNBUN_CLIENT_IP" "$BUNBUN_VERSION""#,
+        )?;
+        let args = ["12"];
+        assert_eq!(
+            resolve_path(
+                &script,
+                &split("12"),
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                Some(&context(&args))
+            )
+            .await?
+            .action,
+            HopAction::Body("gh pr 127.0.0.1 1.0.0".to_owned())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn context_is_opt_in() -> Result<()> {
+        let script = script(r#"printf '{"body": "%s%s"}' "$BUNBUN_QUERY" "$(cat)""#)?;
+        assert_eq!(
+            resolve_path(
+                &script,
+                &split("12"),
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                None
+            )
+            .await?
+            .action,
+            HopAction::Body(String::new())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unread_context_is_ignored() -> Result<()> {
+        let args = ["12"];
+        assert_eq!(
+            resolve_path(
+                Path::new("/bin/echo"),
+                &split(r#"{"body": "a"}"#),
+                None,
+                limits(),
+                &executor(),
+                &Sandbox::default(),
+                Some(&context(&args))
+            )
+            .await?
+            .action,
+            HopAction::Body("a".to_owned())
+        );
+        Ok(())
+    }
 }
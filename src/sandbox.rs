@@ -0,0 +1,158 @@
+use crate::config::Sandbox;
+use crate::BunBunError;
+use tokio::process::Command;
+
+/// Applies the sandbox to a command that's about to be spawned. This must be
+/// done before any environment variables are added to the command, as they
+/// would otherwise be cleared. Fails if the sandbox isn't supported on this
+/// platform.
+#[cfg_attr(target_os = "linux", allow(clippy::unnecessary_wraps))]
+pub fn apply(command: &mut Command, sandbox: &Sandbox) -> Result<(), BunBunError> {
+    if let Some(allowed) = &sandbox.env {
+        command.env_clear();
+        for (key, value) in std::env::vars_os() {
+            if allowed.iter().any(|allowed| key == allowed.as_str()) {
+                command.env(key, value);
+            }
+        }
+    }
+
+    if let Some(dir) = &sandbox.working_dir {
+        command.current_dir(dir);
+    }
+
+    if sandbox.has_process_limits() {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(gid) = sandbox.gid {
+                command.gid(gid);
+            }
+            if let Some(uid) = sandbox.uid {
+                command.uid(uid);
+            }
+            limit_resources(command, sandbox);
+        }
+        #[cfg(not(target_os = "linux"))]
+        return Err(BunBunError::CustomProgram(String::from(
+            "Sandbox limits and user switching are only supported on Linux",
+        )));
+    }
+    Ok(())
+}
+
+/// Makes the command's process set its resource limits, and forbid gaining
+/// privileges if the sandbox asks to, just before it execs. Changing these for
+/// the child alone is only possible from a pre-exec hook, which is unsafe, so
+/// this is the only function in bunbun allowed to use unsafe code.
+#[cfg(target_os = "linux")]
+#[allow(unsafe_code)]
+fn limit_resources(command: &mut Command, sandbox: &Sandbox) {
+    use std::io;
+
+    let limits = [
+        (libc::RLIMIT_CPU, sandbox.cpu_secs),
+        (libc::RLIMIT_AS, sandbox.memory_bytes),
+        (libc::RLIMIT_FSIZE, sandbox.file_size_bytes),
+        (libc::RLIMIT_NPROC, sandbox.max_processes),
+    ];
+    let no_new_privs = sandbox.no_new_privs;
+
+    // SAFETY: The closure runs in the child between fork and exec, so it may
+    // only call async-signal-safe functions. It only calls setrlimit and prctl,
+    // and doesn't allocate.
+    unsafe {
+        command.pre_exec(move || {
+            for (resource, limit) in limits {
+                if let Some(limit) = limit {
+                    let limit = libc::rlimit {
+                        rlim_cur: limit,
+                        rlim_max: limit,
+                    };
+                    if libc::setrlimit(resource, std::ptr::addr_of!(limit)) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+            }
+
+            if no_new_privs && libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod apply {
+    use super::*;
+    use anyhow::Result;
+    use std::path::PathBuf;
+    use std::process::Stdio;
+
+    async fn run(script: &str, sandbox: &Sandbox) -> Result<String> {
+        let mut command = Command::new("/bin/sh");
+        command.args(["-c", script]).stdout(Stdio::piped());
+        apply(&mut command, sandbox)?;
+        let output = command.output().await?;
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    #[tokio::test]
+    async fn env_is_cleared_except_allowed() -> Result<()> {
+        let sandbox = Sandbox {
+            env: Some(vec![String::from("PATH")]),
+            ..Sandbox::default()
+        };
+        let output = run("env", &sandbox).await?;
+        assert!(output.lines().any(|line| line.starts_with("PATH=")));
+        assert!(!output.lines().any(|line| line.starts_with("HOME=")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn default_keeps_env() -> Result<()> {
+        let output = run("echo $PATH", &Sandbox::default()).await?;
+        assert_eq!(output.trim(), std::env::var("PATH")?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn working_dir_is_set() -> Result<()> {
+        let sandbox = Sandbox {
+            working_dir: Some(PathBuf::from("/")),
+            ..Sandbox::default()
+        };
+        assert_eq!(run("pwd", &sandbox).await?, "/\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn limits_are_set() -> Result<()> {
+        let sandbox = Sandbox {
+            file_size_bytes: Some(512 * 1024),
+            cpu_secs: Some(5),
+            ..Sandbox::default()
+        };
+        let output = run("cat /proc/self/limits", &sandbox).await?;
+        let limit = |name| {
+            output
+                .lines()
+                .find(|line| line.starts_with(name))
+                .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        };
+        assert!(limit("Max file size").is_some_and(|line| line[3..5] == ["524288", "524288"]));
+        assert!(limit("Max cpu time").is_some_and(|line| line[3..5] == ["5", "5"]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn no_new_privs_is_set() -> Result<()> {
+        let sandbox = Sandbox {
+            no_new_privs: true,
+            ..Sandbox::default()
+        };
+        let output = run("grep NoNewPrivs /proc/self/status", &sandbox).await?;
+        assert!(output.trim_end().ends_with('1'));
+        Ok(())
+    }
+}
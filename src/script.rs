@@ -0,0 +1,209 @@
+use crate::config::{ExecutableSettings, Route};
+use crate::BunBunError;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Array, Dynamic, Engine, ParseError, Scope, AST};
+use serde::de::{self, Deserialize, Deserializer};
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::sync::Arc;
+use tracing::debug;
+
+/// Limits on a single run of a script.
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptLimits {
+    pub max_operations: u64,
+    pub max_output_bytes: usize,
+}
+
+impl ScriptLimits {
+    /// Returns the limits of the route. Only its output may be limited per
+    /// route, while operations are always limited by the global settings.
+    pub fn new(route: &Route, defaults: &ExecutableSettings) -> Self {
+        Self {
+            max_operations: defaults.script_max_operations,
+            max_output_bytes: route.max_output_bytes.unwrap_or(defaults.max_output_bytes),
+        }
+    }
+}
+
+/// The Rhai source of a script route, which is compiled as the config is
+/// loaded so that syntax errors are reported straight away.
+///
+/// Scripts are given the `query`, the `args` as an array, and the same
+/// `context` executables receive. A script returning a string redirects to
+/// it, while a script returning a map responds like an executable would.
+/// Scripts can't import modules, and their `print` and `debug` output is only
+/// logged.
+#[derive(Debug, Clone)]
+pub struct Script {
+    source: String,
+    ast: Arc<AST>,
+}
+
+impl Script {
+    pub fn compile(source: String) -> Result<Self, ParseError> {
+        let ast = engine(0, 0).compile(&source)?;
+        Ok(Self {
+            source,
+            ast: Arc::new(ast),
+        })
+    }
+
+    /// Runs the script, returning its result as the JSON an executable would
+    /// have responded with. Fails if the script errored, ran for too many
+    /// operations, or built a string or result that was too large.
+    pub fn run(
+        &self,
+        query: String,
+        args: Vec<String>,
+        context: Dynamic,
+        limits: ScriptLimits,
+    ) -> Result<Vec<u8>, BunBunError> {
+        let engine = engine(limits.max_operations, limits.max_output_bytes);
+        let mut scope = Scope::new();
+        scope.push_constant("query", query);
+        scope.push_constant(
+            "args",
+            args.into_iter().map(Dynamic::from).collect::<Array>(),
+        );
+        scope.push_constant("context", context);
+
+        let result: Dynamic = engine.eval_ast_with_scope(&mut scope, &self.ast)?;
+        let output = if result.is_string() {
+            serde_json::json!({ "redirect": result.into_string().unwrap_or_default() })
+        } else if result.is_map() {
+            rhai::serde::from_dynamic(&result)
+                .map_err(|e| BunBunError::InvalidProgramOutput(e.to_string()))?
+        } else {
+            return Err(BunBunError::InvalidProgramOutput(format!(
+                "the script returned a {} instead of a string or map",
+                result.type_name()
+            )));
+        };
+
+        let output = serde_json::to_vec(&output)?;
+        if output.len() > limits.max_output_bytes {
+            return Err(BunBunError::ProgramOutputTooLarge(limits.max_output_bytes));
+        }
+        Ok(output)
+    }
+}
+
+/// Returns an engine that stops scripts after the provided number of
+/// operations. Limiting operations also limits how large arrays and maps can
+/// grow, but strings may double in size with every operation, so they're
+/// limited separately. A limit of zero means no limit.
+fn engine(max_operations: u64, max_string_size: usize) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(max_operations)
+        .set_max_string_size(max_string_size)
+        .set_module_resolver(DummyModuleResolver::new())
+        .on_print(|s| debug!("Script printed: {s}"))
+        .on_debug(|s, _, pos| debug!("Script debug at {pos}: {s}"));
+    engine
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl PartialEq for Script {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Script {}
+
+impl Serialize for Script {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Script {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::compile(String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod run {
+    use super::*;
+    use anyhow::Result;
+
+    fn limits() -> ScriptLimits {
+        ScriptLimits {
+            max_operations: 10_000,
+            max_output_bytes: 1024,
+        }
+    }
+
+    fn run(source: &str, query: &str, limits: ScriptLimits) -> Result<String, BunBunError> {
+        let script = Script::compile(source.to_owned())
+            .map_err(|e| BunBunError::CustomProgram(e.to_string()))?;
+        let args = query.split_ascii_whitespace().map(String::from).collect();
+        let output = script.run(query.to_owned(), args, Dynamic::UNIT, limits)?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    #[test]
+    fn string_is_redirect() -> Result<()> {
+        assert_eq!(
+            run("`https://example.com/${args[1]}`", "a b", limits())?,
+            r#"{"redirect":"https://example.com/b"}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn map_is_response() -> Result<()> {
+        assert_eq!(
+            run("#{ body: query, status: 201 }", "a b", limits())?,
+            r#"{"body":"a b","status":201}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn other_results_are_rejected() {
+        assert!(matches!(
+            run("42", "", limits()),
+            Err(BunBunError::InvalidProgramOutput(_))
+        ));
+    }
+
+    #[test]
+    fn endless_loop_is_stopped() {
+        assert!(matches!(
+            run("loop {}", "", limits()),
+            Err(BunBunError::Script(_))
+        ));
+    }
+
+    #[test]
+    fn large_strings_are_rejected() {
+        let limits = ScriptLimits {
+            max_output_bytes: 64,
+            ..limits()
+        };
+        assert!(run(r#"let s = "a"; s.pad(8, "b"); s"#, "", limits).is_ok());
+        assert!(matches!(
+            run(r#"let s = "a"; s.pad(128, "b"); s"#, "", limits),
+            Err(BunBunError::Script(_))
+        ));
+    }
+
+    #[test]
+    fn syntax_errors_are_rejected() {
+        assert!(Script::compile(String::from("let = 1")).is_err());
+    }
+
+    #[test]
+    fn modules_cant_be_imported() {
+        assert!(run(r#"import "x" as x; """#, "", limits()).is_err());
+    }
+}
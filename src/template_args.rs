@@ -1,15 +1,58 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 
-use percent_encoding::PercentEncode;
 use serde::Serialize;
 
-pub fn query(query: PercentEncode<'_>) -> impl Serialize + '_ {
+use crate::encoding::Encoding;
+use crate::routes::Suggestion;
+
+pub fn query<'a>(
+    query: &'a str,
+    args: &'a [&'a str],
+    params: &'a [(&'a str, &'a str)],
+    captures: &'a HashMap<String, String>,
+    encoding: Encoding,
+) -> impl Serialize + 'a {
+    #[derive(Serialize)]
+    struct RawArgs<'a> {
+        query: &'a str,
+        args: &'a [&'a str],
+        #[serde(flatten)]
+        params: HashMap<&'a str, &'a str>,
+        captures: &'a HashMap<String, String>,
+    }
+
     #[derive(Serialize)]
     struct TemplateArgs<'a> {
         query: Cow<'a, str>,
+        args: Vec<Cow<'a, str>>,
+        argc: usize,
+        #[serde(flatten)]
+        params: HashMap<&'a str, Cow<'a, str>>,
+        captures: HashMap<&'a str, Cow<'a, str>>,
+        raw: RawArgs<'a>,
+        encoding: Encoding,
     }
+
     TemplateArgs {
-        query: query.into(),
+        query: encoding.encode(query),
+        args: args.iter().map(|arg| encoding.encode(arg)).collect(),
+        argc: args.len(),
+        params: params
+            .iter()
+            .map(|(name, value)| (*name, encoding.encode(value)))
+            .collect(),
+        captures: captures
+            .iter()
+            .map(|(name, value)| (name.as_str(), encoding.encode(value)))
+            .collect(),
+        raw: RawArgs {
+            query,
+            args,
+            params: params.iter().copied().collect(),
+            captures,
+        },
+        encoding,
     }
 }
 
@@ -20,3 +63,21 @@ pub fn hostname(hostname: &'_ str) -> impl Serialize + '_ {
     }
     TemplateArgs { hostname }
 }
+
+pub fn open_all<'a>(query: &'a str, urls: &'a [String]) -> impl Serialize + 'a {
+    #[derive(Serialize)]
+    pub struct TemplateArgs<'a> {
+        pub query: &'a str,
+        pub urls: &'a [String],
+    }
+    TemplateArgs { query, urls }
+}
+
+pub fn not_found<'a>(query: &'a str, suggestions: &'a [Suggestion<'a>]) -> impl Serialize + 'a {
+    #[derive(Serialize)]
+    pub struct TemplateArgs<'a> {
+        pub query: &'a str,
+        pub suggestions: &'a [Suggestion<'a>],
+    }
+    TemplateArgs { query, suggestions }
+}
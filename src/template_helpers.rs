@@ -0,0 +1,153 @@
+use crate::encoding::Encoding;
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderError,
+};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Renders every argument starting from the provided (zero-based) index,
+/// joined by a space and encoded with the route's encoding. For example,
+/// `{{rest 1}}` renders all but the first argument. Renders nothing if the
+/// index is past the last argument.
+pub fn rest(
+    h: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let start = h
+        .param(0)
+        .and_then(|param| param.value().as_u64())
+        .ok_or_else(|| RenderError::new("rest requires an argument index"))?;
+    let args = ctx
+        .data()
+        .pointer("/raw/args")
+        .and_then(Value::as_array)
+        .ok_or_else(|| RenderError::new("rest can only be used in routes"))?;
+    let encoding = ctx
+        .data()
+        .get("encoding")
+        .and_then(|encoding| Encoding::deserialize(encoding).ok())
+        .ok_or_else(|| RenderError::new("rest can only be used in routes"))?;
+
+    let rest = args
+        .iter()
+        .skip(usize::try_from(start).unwrap_or(usize::MAX))
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+    out.write(&encoding.encode(&rest))?;
+    Ok(())
+}
+
+/// Renders its parameter with a specific encoding, regardless of the encoding
+/// used by the route. This should be used with the unencoded values, such as
+/// `{{path_segment raw.args.[0]}}`.
+pub struct Encode(pub Encoding);
+
+impl HelperDef for Encode {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let value = h
+            .param(0)
+            .ok_or_else(|| RenderError::new(format!("{} requires a value", h.name())))?
+            .value();
+        let value = value
+            .as_str()
+            .map_or_else(|| value.to_string(), ToOwned::to_owned);
+        out.write(&self.0.encode(&value))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod rest {
+    use super::rest;
+    use anyhow::Result;
+    use handlebars::Handlebars;
+    use serde_json::json;
+
+    fn render(template: &str, args: &[&str], encoding: &str) -> Result<String> {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("rest", Box::new(rest));
+        Ok(handlebars.render_template(
+            template,
+            &json!({ "raw": { "args": args }, "encoding": encoding }),
+        )?)
+    }
+
+    #[test]
+    fn renders_all_args_from_index() -> Result<()> {
+        assert_eq!(
+            render("{{rest 0}}", &["a", "b", "c"], "fragment")?,
+            "a%20b%20c"
+        );
+        assert_eq!(render("{{rest 1}}", &["a", "b", "c"], "fragment")?, "b%20c");
+        assert_eq!(render("{{rest 2}}", &["a", "b", "c"], "fragment")?, "c");
+        Ok(())
+    }
+
+    #[test]
+    fn index_past_args_renders_nothing() -> Result<()> {
+        assert_eq!(render("{{rest 3}}", &["a", "b", "c"], "fragment")?, "");
+        assert_eq!(render("{{rest 0}}", &[], "fragment")?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn missing_index_fails() {
+        assert!(render("{{rest}}", &["a"], "fragment").is_err());
+        assert!(render("{{rest \"a\"}}", &["a"], "fragment").is_err());
+    }
+
+    #[test]
+    fn uses_route_encoding() -> Result<()> {
+        assert_eq!(render("{{rest 0}}", &["a/b", "c"], "form")?, "a%2Fb+c");
+        assert_eq!(render("{{rest 0}}", &["a/b", "c"], "raw")?, "a/b c");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod encode {
+    use super::Encode;
+    use crate::encoding::Encoding;
+    use anyhow::Result;
+    use handlebars::Handlebars;
+    use serde_json::json;
+
+    fn render(template: &str) -> Result<String> {
+        let mut handlebars = Handlebars::new();
+        for (name, encoding) in Encoding::HELPERS {
+            handlebars.register_helper(name, Box::new(Encode(encoding)));
+        }
+        Ok(handlebars.render_template(template, &json!({ "value": "a b/c", "count": 2 }))?)
+    }
+
+    #[test]
+    fn encodes_with_helper_encoding() -> Result<()> {
+        assert_eq!(render("{{fragment value}}")?, "a%20b/c");
+        assert_eq!(render("{{path_segment value}}")?, "a%20b%2Fc");
+        assert_eq!(render("{{query_component value}}")?, "a%20b%2Fc");
+        assert_eq!(render("{{form value}}")?, "a+b%2Fc");
+        Ok(())
+    }
+
+    #[test]
+    fn encodes_non_strings() -> Result<()> {
+        assert_eq!(render("{{form count}}")?, "2");
+        Ok(())
+    }
+
+    #[test]
+    fn missing_value_fails() {
+        assert!(render("{{form}}").is_err());
+    }
+}
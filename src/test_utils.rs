@@ -0,0 +1,39 @@
+//! Fixtures shared by the tests of several modules.
+
+use crate::config::{ExecutableSettings, Route};
+use crate::executor::{ExecLimits, Executor};
+use crate::trie::RouteTrie;
+use anyhow::Result;
+use std::fs::Permissions;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
+use tempfile::{NamedTempFile, TempPath};
+
+pub fn generate_routes(routes: &[(&'static str, Route)]) -> RouteTrie {
+    let mut trie = RouteTrie::default();
+    for (keyword, route) in routes {
+        trie.insert(keyword, route.clone());
+    }
+    trie
+}
+
+pub fn limits() -> ExecLimits {
+    ExecLimits {
+        timeout: Duration::from_secs(10),
+        max_output_bytes: 1024,
+    }
+}
+
+pub fn executor() -> Executor {
+    Executor::new(&ExecutableSettings::default())
+}
+
+/// Writes an executable shell script with the provided body.
+pub fn script(body: &str) -> Result<TempPath> {
+    let mut file = NamedTempFile::new()?;
+    writeln!(file, "#!/bin/sh\n{body}")?;
+    file.as_file()
+        .set_permissions(Permissions::from_mode(0o755))?;
+    Ok(file.into_temp_path())
+}
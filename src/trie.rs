@@ -0,0 +1,151 @@
+use crate::config::Route;
+use std::collections::HashMap;
+
+/// A trie of routes keyed on whitespace-delimited tokens. This lets keywords
+/// that span multiple words (e.g. `gh pr`) coexist with shorter keywords that
+/// they share a prefix with (e.g. `gh`).
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RouteTrie {
+    route: Option<Route>,
+    children: HashMap<String, Self>,
+}
+
+impl RouteTrie {
+    /// Inserts a route under the provided keyword, returning the route that
+    /// was previously registered under it, if any.
+    pub fn insert(&mut self, keyword: &str, route: Route) -> Option<Route> {
+        keyword
+            .split_ascii_whitespace()
+            .fold(self, |node, token| {
+                node.children.entry(token.to_owned()).or_default()
+            })
+            .route
+            .replace(route)
+    }
+
+    /// Returns the route registered under exactly the provided keyword.
+    pub fn get(&self, keyword: &str) -> Option<&Route> {
+        keyword
+            .split_ascii_whitespace()
+            .try_fold(self, |node, token| node.children.get(token))?
+            .route
+            .as_ref()
+    }
+
+    /// Returns every route whose keyword is a prefix of the provided tokens,
+    /// alongside the number of tokens its keyword consumed. Matches are ordered
+    /// from longest to shortest keyword.
+    pub fn prefixes<'a>(&'a self, tokens: &[&str]) -> Vec<(&'a Route, usize)> {
+        let mut matches = Vec::new();
+        let mut node = self;
+        for (consumed, token) in tokens.iter().enumerate() {
+            match node.children.get(*token) {
+                Some(child) => node = child,
+                None => break,
+            }
+
+            if let Some(route) = &node.route {
+                matches.push((route, consumed + 1));
+            }
+        }
+        matches.reverse();
+        matches
+    }
+
+    /// Returns every route in the trie alongside its full keyword, sorted by
+    /// keyword.
+    pub fn routes(&self) -> Vec<(String, &Route)> {
+        fn collect<'a>(node: &'a RouteTrie, keyword: &str, routes: &mut Vec<(String, &'a Route)>) {
+            if let Some(route) = &node.route {
+                routes.push((keyword.to_owned(), route));
+            }
+
+            for (token, child) in &node.children {
+                if keyword.is_empty() {
+                    collect(child, token, routes);
+                } else {
+                    collect(child, &format!("{keyword} {token}"), routes);
+                }
+            }
+        }
+
+        let mut routes = Vec::new();
+        collect(self, "", &mut routes);
+        routes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        routes
+    }
+}
+
+#[cfg(test)]
+mod route_trie {
+    use super::*;
+
+    fn generate_trie(routes: &[(&'static str, &'static str)]) -> RouteTrie {
+        let mut trie = RouteTrie::default();
+        for (keyword, path) in routes {
+            trie.insert(keyword, Route::from(*path));
+        }
+        trie
+    }
+
+    #[test]
+    fn insert_returns_overridden_route() {
+        let mut trie = generate_trie(&[("a b", "1")]);
+        assert_eq!(trie.insert("a b", Route::from("2")), Some(Route::from("1")));
+        assert_eq!(trie.insert("a", Route::from("3")), None);
+    }
+
+    #[test]
+    fn get_requires_exact_keyword() {
+        let trie = generate_trie(&[("a b", "1"), ("a", "2")]);
+        assert_eq!(trie.get("a b"), Some(&Route::from("1")));
+        assert_eq!(trie.get("a  b"), Some(&Route::from("1")));
+        assert_eq!(trie.get("a"), Some(&Route::from("2")));
+        assert_eq!(trie.get("a b c"), None);
+        assert_eq!(trie.get("b"), None);
+    }
+
+    #[test]
+    fn get_skips_intermediate_nodes() {
+        let trie = generate_trie(&[("a b c", "1")]);
+        assert_eq!(trie.get("a"), None);
+        assert_eq!(trie.get("a b"), None);
+    }
+
+    #[test]
+    fn prefixes_are_longest_first() {
+        let trie = generate_trie(&[("a", "1"), ("a b c", "2"), ("a b", "3"), ("b", "4")]);
+        assert_eq!(
+            trie.prefixes(&["a", "b", "c", "d"]),
+            vec![
+                (&Route::from("2"), 3),
+                (&Route::from("3"), 2),
+                (&Route::from("1"), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn prefixes_stop_at_first_mismatch() {
+        let trie = generate_trie(&[("a", "1"), ("a c", "2")]);
+        assert_eq!(
+            trie.prefixes(&["a", "b", "c"]),
+            vec![(&Route::from("1"), 1)]
+        );
+        assert!(trie.prefixes(&["b", "a"]).is_empty());
+        assert!(trie.prefixes(&[]).is_empty());
+    }
+
+    #[test]
+    fn routes_include_full_keywords() {
+        let trie = generate_trie(&[("b", "1"), ("a b c", "2"), ("a", "3")]);
+        assert_eq!(
+            trie.routes(),
+            vec![
+                (String::from("a"), &Route::from("3")),
+                (String::from("a b c"), &Route::from("2")),
+                (String::from("b"), &Route::from("1")),
+            ]
+        );
+    }
+}
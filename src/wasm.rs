@@ -0,0 +1,256 @@
+use crate::config::{ExecutableSettings, Route, RouteType};
+use crate::BunBunError;
+use std::collections::HashMap;
+use std::path::Path;
+use wasmi::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use wasmi_wasi::{WasiCtx, WasiCtxBuilder};
+
+/// Limits on a single call into a module.
+#[derive(Debug, Clone, Copy)]
+pub struct WasmLimits {
+    pub fuel: u64,
+    pub max_memory_bytes: usize,
+    pub max_output_bytes: usize,
+}
+
+impl WasmLimits {
+    /// Returns the limits of the route. Only its output may be limited per
+    /// route, while fuel and memory are always limited by the global settings.
+    pub fn new(route: &Route, defaults: &ExecutableSettings) -> Self {
+        Self {
+            fuel: defaults.wasm_fuel,
+            max_memory_bytes: defaults.wasm_max_memory_bytes,
+            max_output_bytes: route.max_output_bytes.unwrap_or(defaults.max_output_bytes),
+        }
+    }
+}
+
+/// The data of a module instance.
+struct Host {
+    wasi: WasiCtx,
+    limits: StoreLimits,
+}
+
+/// A compiled WASI module of a wasm route. Every call runs in a new instance,
+/// so hops can't affect each other. Instances may only write to stderr, and
+/// can't access the filesystem or environment.
+///
+/// Modules must export their `memory`, an `alloc` function that takes a
+/// length and returns a pointer to that many free bytes, and a `resolve`
+/// function. `resolve` takes a pointer to and length of its input, and returns
+/// a pointer to its output in the upper 32 bits and the output's length in the
+/// lower 32 bits. Modules with an `_initialize` export have it called first.
+#[derive(Clone)]
+pub struct WasmModule {
+    module: Module,
+    linker: Linker<Host>,
+}
+
+impl std::fmt::Debug for WasmModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmModule").finish_non_exhaustive()
+    }
+}
+
+impl WasmModule {
+    /// Compiles the module, which may be in either the binary or text format.
+    pub fn load(engine: &Engine, path: &Path) -> Result<Self, BunBunError> {
+        let module = Module::new(engine, std::fs::read(path)?)?;
+        let mut linker = Linker::new(engine);
+        wasmi_wasi::add_to_linker(&mut linker, |host: &mut Host| &mut host.wasi)
+            .map_err(|e| BunBunError::CustomProgram(e.to_string()))?;
+        Ok(Self { module, linker })
+    }
+
+    /// Calls `resolve` with the input, returning its output. Fails if the
+    /// instance ran out of fuel, grew its memory past the limit or returned too
+    /// much output.
+    pub fn call(&self, input: &[u8], limits: WasmLimits) -> Result<Vec<u8>, BunBunError> {
+        let host = Host {
+            wasi: WasiCtxBuilder::new().inherit_stderr().build(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(limits.max_memory_bytes)
+                .build(),
+        };
+        let mut store = Store::new(self.module.engine(), host);
+        store.limiter(|host| &mut host.limits);
+        store.set_fuel(limits.fuel)?;
+
+        let instance = self
+            .linker
+            .instantiate_and_start(&mut store, &self.module)?;
+        if let Ok(initialize) = instance.get_typed_func::<(), ()>(&store, "_initialize") {
+            initialize.call(&mut store, ())?;
+        }
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| invalid_module("no exported memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+        let resolve = instance.get_typed_func::<(i32, i32), i64>(&store, "resolve")?;
+
+        let input_len =
+            i32::try_from(input.len()).map_err(|_| invalid_module("input is too large"))?;
+        let input_ptr = alloc.call(&mut store, input_len)?;
+        memory
+            .write(&mut store, wasm_usize(input_ptr), input)
+            .map_err(|_| invalid_module("alloc returned an invalid pointer"))?;
+
+        // Both halves are unsigned 32-bit integers
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let (output_ptr, output_len) = {
+            let output = resolve.call(&mut store, (input_ptr, input_len))? as u64;
+            ((output >> 32) as usize, (output as u32) as usize)
+        };
+        if output_len > limits.max_output_bytes {
+            return Err(BunBunError::ProgramOutputTooLarge(limits.max_output_bytes));
+        }
+
+        let mut output = vec![0; output_len];
+        memory
+            .read(&store, output_ptr, &mut output)
+            .map_err(|_| invalid_module("resolve returned an invalid pointer"))?;
+        Ok(output)
+    }
+}
+
+/// Interprets a 32-bit pointer from a module as unsigned.
+#[allow(clippy::cast_sign_loss)]
+const fn wasm_usize(ptr: i32) -> usize {
+    ptr as u32 as usize
+}
+
+fn invalid_module(reason: &str) -> BunBunError {
+    BunBunError::InvalidProgramOutput(format!("the module's {reason}"))
+}
+
+/// Compiles the module of every wasm route, keyed by path.
+pub fn modules<'a>(
+    routes: impl IntoIterator<Item = &'a Route>,
+) -> Result<HashMap<String, WasmModule>, BunBunError> {
+    let mut config = Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+
+    let mut modules = HashMap::new();
+    for route in routes {
+        if route.route_type == RouteType::Wasm && !modules.contains_key(&route.path) {
+            let module = WasmModule::load(&engine, Path::new(&route.path)).map_err(|e| {
+                BunBunError::CustomProgram(format!("Failed to load {}: {e}", route.path))
+            })?;
+            modules.insert(route.path.clone(), module);
+        }
+    }
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod call {
+    use super::*;
+    use anyhow::Result;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    /// A module whose `resolve` is the provided function body, with a bump
+    /// allocator starting at the second page.
+    fn module(resolve: &str) -> Result<WasmModule> {
+        let mut file = NamedTempFile::new()?;
+        write!(
+            file,
+            r#"(module
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (memory (export "memory") 2)
+                (global $next (mut i32) (i32.const 65536))
+                (data (i32.const 0) "{{\"body\": \"hi\"}}")
+                (func (export "alloc") (param $len i32) (result i32)
+                    (global.get $next)
+                    (global.set $next (i32.add (global.get $next) (local.get $len))))
+                (func (export "resolve") (param $ptr i32) (param $len i32) (result i64)
+                    {resolve}))"#
+        )?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Ok(WasmModule::load(&Engine::new(&config), file.path())?)
+    }
+
+    fn limits() -> WasmLimits {
+        WasmLimits {
+            fuel: 1_000_000,
+            max_memory_bytes: 1024 * 1024,
+            max_output_bytes: 1024,
+        }
+    }
+
+    /// Returns the pointer and length packed for returning from `resolve`.
+    const ECHO: &str = "(i64.or
+        (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+        (i64.extend_i32_u (local.get $len)))";
+
+    #[test]
+    fn output_is_returned() -> Result<()> {
+        let module = module("(i64.const 14)")?;
+        assert_eq!(module.call(b"", limits())?, br#"{"body": "hi"}"#);
+        Ok(())
+    }
+
+    #[test]
+    fn input_is_passed() -> Result<()> {
+        let module = module(ECHO)?;
+        assert_eq!(module.call(b"a b", limits())?, b"a b");
+        Ok(())
+    }
+
+    #[test]
+    fn endless_loop_runs_out_of_fuel() -> Result<()> {
+        let module = module("(loop (br 0)) (i64.const 0)")?;
+        assert!(matches!(
+            module.call(b"", limits()),
+            Err(BunBunError::Wasm(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn memory_growth_is_limited() -> Result<()> {
+        // Traps if growing by 4 MiB failed
+        let module = module(
+            "(if (i32.eq (memory.grow (i32.const 64)) (i32.const -1)) (then (unreachable)))
+             (i64.const 14)",
+        )?;
+        assert!(matches!(
+            module.call(b"", limits()),
+            Err(BunBunError::Wasm(_))
+        ));
+        let limits = WasmLimits {
+            max_memory_bytes: 8 * 1024 * 1024,
+            ..limits()
+        };
+        assert!(module.call(b"", limits).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn large_output_is_rejected() -> Result<()> {
+        let module = module("(i64.const 14)")?;
+        let limits = WasmLimits {
+            max_output_bytes: 4,
+            ..limits()
+        };
+        assert!(matches!(
+            module.call(b"", limits),
+            Err(BunBunError::ProgramOutputTooLarge(4))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_bounds_output_is_rejected() -> Result<()> {
+        // Four bytes at the last possible address
+        let module = module("(i64.const 0x7fffffff00000004)")?;
+        assert!(matches!(
+            module.call(b"", limits()),
+            Err(BunBunError::InvalidProgramOutput(_))
+        ));
+        Ok(())
+    }
+}
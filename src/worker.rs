@@ -0,0 +1,345 @@
+use crate::config::{Route, RouteType, Sandbox};
+use crate::executor::{ExecLimits, Executor};
+use crate::sandbox;
+use crate::BunBunError;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::timeout;
+use tracing::{debug, warn};
+
+/// Long-lived processes of a worker route. Processes are started as they're
+/// needed rather than when the config is loaded, and one that crashed or
+/// misbehaved is replaced by a new process on the next request.
+#[derive(Debug)]
+pub struct WorkerPool {
+    path: PathBuf,
+    sandbox: Sandbox,
+    /// Limits how many requests are handled at once, and therefore how many
+    /// processes are alive.
+    permits: Semaphore,
+    idle: Mutex<Vec<Worker>>,
+}
+
+impl WorkerPool {
+    pub fn new(path: PathBuf, sandbox: Sandbox, size: usize) -> Self {
+        Self {
+            path,
+            sandbox,
+            permits: Semaphore::new(size),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Sends the request to a worker as a single line, returning the line it
+    /// responded with. Like any other executable, the request must wait for
+    /// the executor before a worker may handle it, but only once a worker is
+    /// free, so that hops queued for a busy pool don't hold up other routes.
+    /// Time spent waiting for a free worker and the executor counts towards the
+    /// timeout. Workers that fail
+    /// to respond in time, or with a valid line, are killed.
+    pub async fn request(
+        &self,
+        request: &[u8],
+        limits: ExecLimits,
+        executor: &Executor,
+    ) -> Result<Vec<u8>, BunBunError> {
+        let run = async {
+            let _permit = self
+                .permits
+                .acquire()
+                .await
+                .map_err(|_| BunBunError::ExecutorBusy)?;
+            let _executor_permit = executor.acquire().await?;
+            let mut worker = match self.take_idle().await {
+                Some(worker) => worker,
+                None => self.spawn()?,
+            };
+            let response = worker.exchange(request, limits.max_output_bytes).await?;
+            self.idle.lock().await.push(worker);
+            Ok(response)
+        };
+
+        timeout(limits.timeout, run)
+            .await
+            .map_err(|_| BunBunError::ProgramTimedOut(limits.timeout))?
+    }
+
+    /// Returns an idle worker that's still alive, if there is one.
+    async fn take_idle(&self) -> Option<Worker> {
+        let mut idle = self.idle.lock().await;
+        while let Some(mut worker) = idle.pop() {
+            match worker.child.try_wait() {
+                Ok(None) => return Some(worker),
+                Ok(Some(status)) => {
+                    warn!(
+                        "Worker {} exited with {status}, restarting",
+                        self.path.display()
+                    );
+                }
+                Err(e) => warn!("Failed to check on worker {}: {e}", self.path.display()),
+            }
+        }
+        None
+    }
+
+    fn spawn(&self) -> Result<Worker, BunBunError> {
+        debug!("Starting worker {}", self.path.display());
+        let mut command = Command::new(self.path.canonicalize()?);
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        sandbox::apply(&mut command, &self.sandbox)?;
+        let mut child = command.spawn()?;
+        let stdin = child.stdin.take().ok_or(BunBunError::WorkerExited)?;
+        let stdout = child.stdout.take().ok_or(BunBunError::WorkerExited)?;
+        Ok(Worker {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+}
+
+/// A running worker process. The process is killed when this is dropped.
+#[derive(Debug)]
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Worker {
+    /// Writes the request as a line, and reads a line of at most the provided
+    /// number of bytes in response.
+    async fn exchange(&mut self, request: &[u8], max_bytes: usize) -> Result<Vec<u8>, BunBunError> {
+        self.stdin.write_all(request).await?;
+        self.stdin.write_all(b"\n").await?;
+        self.stdin.flush().await?;
+
+        // Allow for the newline, and for one more byte to detect long lines
+        let limit = u64::try_from(max_bytes)
+            .unwrap_or(u64::MAX)
+            .saturating_add(2);
+        let mut line = Vec::new();
+        (&mut self.stdout)
+            .take(limit)
+            .read_until(b'\n', &mut line)
+            .await?;
+
+        if line.last() != Some(&b'\n') {
+            return Err(if line.len() > max_bytes {
+                BunBunError::ProgramOutputTooLarge(max_bytes)
+            } else {
+                BunBunError::WorkerExited
+            });
+        }
+
+        line.pop();
+        if line.len() > max_bytes {
+            return Err(BunBunError::ProgramOutputTooLarge(max_bytes));
+        }
+        Ok(line)
+    }
+}
+
+/// Creates a pool for every worker route, keyed by path. Routes sharing a path
+/// share a pool, whose size is the largest of their pool sizes. Fails if routes
+/// sharing a path have different sandboxes, as their pool can only use one.
+pub fn pools<'a>(
+    routes: impl IntoIterator<Item = &'a Route>,
+) -> Result<HashMap<String, WorkerPool>, BunBunError> {
+    let mut pools: HashMap<&str, (&Sandbox, usize)> = HashMap::new();
+    for route in routes {
+        if route.route_type != RouteType::Worker {
+            continue;
+        }
+
+        let (sandbox, size) = pools
+            .entry(route.path.as_str())
+            .or_insert((&route.sandbox, 1));
+        if *sandbox != &route.sandbox {
+            return Err(BunBunError::CustomProgram(format!(
+                "Worker routes for {} have different sandboxes",
+                route.path
+            )));
+        }
+        *size = route.pool_size.unwrap_or(1).max(*size);
+    }
+
+    Ok(pools
+        .into_iter()
+        .map(|(path, (sandbox, size))| {
+            let pool = WorkerPool::new(PathBuf::from(path), sandbox.clone(), size);
+            (path.to_owned(), pool)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod request {
+    use super::*;
+    use crate::config::ExecutableSettings;
+    use crate::test_utils::{executor, limits, script};
+    use anyhow::Result;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn worker_is_reused() -> Result<()> {
+        // Each response counts how many requests the process has seen
+        let path = script("n=0\nwhile read line; do n=$((n+1)); echo \"$n $line\"; done")?;
+        let pool = WorkerPool::new(path.to_path_buf(), Sandbox::default(), 1);
+        assert_eq!(pool.request(b"a", limits(), &executor()).await?, b"1 a");
+        assert_eq!(pool.request(b"b", limits(), &executor()).await?, b"2 b");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn crashed_worker_is_restarted() -> Result<()> {
+        let path = script("read line; echo \"$line\"")?;
+        let pool = WorkerPool::new(path.to_path_buf(), Sandbox::default(), 1);
+        assert_eq!(pool.request(b"a", limits(), &executor()).await?, b"a");
+        for worker in pool.idle.lock().await.iter_mut() {
+            worker.child.wait().await?;
+        }
+        assert_eq!(pool.request(b"b", limits(), &executor()).await?, b"b");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exiting_without_response_is_err() -> Result<()> {
+        let path = script("read line; printf partial")?;
+        let pool = WorkerPool::new(path.to_path_buf(), Sandbox::default(), 1);
+        assert!(matches!(
+            pool.request(b"a", limits(), &executor()).await,
+            Err(BunBunError::WorkerExited)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn slow_worker_times_out() -> Result<()> {
+        let path = script("while read line; do sleep 10; done")?;
+        let pool = WorkerPool::new(path.to_path_buf(), Sandbox::default(), 1);
+        let limits = ExecLimits {
+            timeout: Duration::from_millis(100),
+            ..limits()
+        };
+        assert!(matches!(
+            pool.request(b"a", limits, &executor()).await,
+            Err(BunBunError::ProgramTimedOut(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn busy_executor_is_err() -> Result<()> {
+        let path = script("while read line; do echo \"$line\"; done")?;
+        let pool = WorkerPool::new(path.to_path_buf(), Sandbox::default(), 1);
+        let executor = Executor::new(&ExecutableSettings {
+            max_concurrent: 1,
+            max_queued: 0,
+            ..ExecutableSettings::default()
+        });
+        let permit = executor.acquire().await?;
+        assert!(matches!(
+            pool.request(b"a", limits(), &executor).await,
+            Err(BunBunError::ExecutorBusy)
+        ));
+        drop(permit);
+        assert_eq!(pool.request(b"a", limits(), &executor).await?, b"a");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn busy_pool_doesnt_block_executor() -> Result<()> {
+        let path = script("while read line; do sleep 10; done")?;
+        let pool = Arc::new(WorkerPool::new(path.to_path_buf(), Sandbox::default(), 1));
+        let executor = Arc::new(Executor::new(&ExecutableSettings {
+            max_concurrent: 2,
+            max_queued: 0,
+            ..ExecutableSettings::default()
+        }));
+
+        // One hop is handled by the only worker, while the others wait for it
+        let hops = (0..3)
+            .map(|_| {
+                let pool = Arc::clone(&pool);
+                let executor = Arc::clone(&executor);
+                tokio::spawn(async move { pool.request(b"a", limits(), &executor).await })
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..hops.len() {
+            tokio::task::yield_now().await;
+        }
+
+        // An exec route may still run
+        assert!(executor.acquire().await.is_ok());
+        hops.iter().for_each(tokio::task::JoinHandle::abort);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn long_line_is_rejected() -> Result<()> {
+        let path = script("while read line; do echo \"$line$line\"; done")?;
+        let pool = WorkerPool::new(path.to_path_buf(), Sandbox::default(), 1);
+        let limits = ExecLimits {
+            max_output_bytes: 4,
+            ..limits()
+        };
+        assert_eq!(pool.request(b"ab", limits, &executor()).await?, b"abab");
+        assert!(matches!(
+            pool.request(b"abc", limits, &executor()).await,
+            Err(BunBunError::ProgramOutputTooLarge(4))
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod pools {
+    use super::*;
+
+    fn worker(path: &'static str, pool_size: Option<usize>) -> Route {
+        Route {
+            route_type: RouteType::Worker,
+            pool_size,
+            ..Route::from(path)
+        }
+    }
+
+    #[test]
+    fn shared_paths_use_largest_size() -> Result<(), BunBunError> {
+        let routes = [
+            worker("/a", Some(2)),
+            worker("/a", None),
+            worker("/b", None),
+            Route::from("https://example.com"),
+        ];
+        let pools = pools(&routes)?;
+        assert_eq!(pools.len(), 2);
+        assert_eq!(pools["/a"].permits.available_permits(), 2);
+        assert_eq!(pools["/b"].permits.available_permits(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn shared_paths_with_different_sandboxes_fail() {
+        let sandboxed = Route {
+            sandbox: Sandbox {
+                uid: Some(1000),
+                gid: Some(1000),
+                ..Sandbox::default()
+            },
+            ..worker("/a", None)
+        };
+        assert!(pools(&[worker("/a", None), sandboxed.clone()]).is_err());
+        assert!(pools(&[sandboxed.clone(), sandboxed]).is_ok());
+    }
+}